@@ -0,0 +1,61 @@
+//! Tree build time and payload size of `HuffmanTree::build_fast` against `build`, on 4 KiB
+//! slices of this crate's source and of the benchmark's own executable.
+//! Run with `cargo bench --bench build_fast`.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use compression::{HuffmanArchive, HuffmanTree};
+
+const SLICE_LEN: usize = 4 << 10;
+const ROUNDS: u32 = 2000;
+
+fn time_builds(slices: &[&[u8]], build: fn(&[u8]) -> HuffmanTree) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        for slice in slices {
+            black_box(build(black_box(slice)));
+        }
+    }
+    start.elapsed()
+}
+
+fn payload_bits(slices: &[&[u8]], build: fn(&[u8]) -> HuffmanTree) -> usize {
+    slices
+        .iter()
+        .map(|slice| HuffmanArchive::payload_bit_len_with_tree(slice, &build(slice)).unwrap())
+        .sum()
+}
+
+fn compare(name: &str, data: &[u8]) {
+    let slices: Vec<&[u8]> = data.chunks_exact(SLICE_LEN).take(16).collect();
+
+    let exact = time_builds(&slices, HuffmanTree::build);
+    let fast = time_builds(&slices, HuffmanTree::build_fast);
+    let exact_bits = payload_bits(&slices, HuffmanTree::build);
+    let fast_bits = payload_bits(&slices, HuffmanTree::build_fast);
+
+    println!(
+        "{name:>6}: {} slices, build {:.1} µs, build_fast {:.1} µs ({:+.1}% time), payload {:+.2}%",
+        slices.len(),
+        exact.as_secs_f64() * 1e6 / (ROUNDS as f64 * slices.len() as f64),
+        fast.as_secs_f64() * 1e6 / (ROUNDS as f64 * slices.len() as f64),
+        (fast.as_secs_f64() / exact.as_secs_f64() - 1.0) * 100.0,
+        (fast_bits as f64 / exact_bits as f64 - 1.0) * 100.0,
+    );
+}
+
+fn main() {
+    let source = [
+        include_str!("../src/archive.rs"),
+        include_str!("../src/header.rs"),
+        include_str!("../src/tree.rs"),
+        include_str!("../src/main.rs"),
+    ]
+    .concat();
+    compare("source", source.as_bytes());
+
+    let binary = std::fs::read(std::env::current_exe().unwrap()).unwrap();
+    // Skip past the ELF header and into code and data
+    compare("binary", &binary[binary.len() / 4..]);
+}
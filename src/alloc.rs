@@ -0,0 +1,72 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Global allocator counting allocations, so tests can pin how many `compress` and
+/// `decompress` make. Install it in the test binary:
+///
+/// ```ignore
+/// #[global_allocator]
+/// static ALLOC: compression::CountingAllocator = compression::CountingAllocator::new();
+/// ```
+///
+/// The count is shared by every thread, run allocation tests single-threaded.
+///
+/// Allocations in `compress` and `decompress` are bounded by the size of the alphabet
+/// rather than the input: one per tree node and code, plus the output buffer growing
+/// geometrically. 4 KiB and 58 KiB of Rust source take about 480 and 740 to compress and 160
+/// and 270 to decompress, the longer one using more distinct bytes. Input using every byte
+/// value takes about 2100 and 790 whatever its length, `tests/alloc.rs` holds it to that.
+pub struct CountingAllocator {
+    allocations: AtomicUsize,
+}
+
+impl CountingAllocator {
+    pub const fn new() -> Self {
+        Self {
+            allocations: AtomicUsize::new(0),
+        }
+    }
+
+    /// Allocations (including reallocations) since the last `reset`
+    pub fn allocations(&self) -> usize {
+        self.allocations.load(Ordering::Relaxed)
+    }
+
+    pub fn reset(&self) {
+        self.allocations.store(0, Ordering::Relaxed);
+    }
+
+    /// Runs `f` and returns its result with the allocations it made
+    pub fn count<T>(&self, f: impl FnOnce() -> T) -> (T, usize) {
+        let before = self.allocations();
+        let result = f();
+        (result, self.allocations() - before)
+    }
+}
+
+impl Default for CountingAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.allocations.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        self.allocations.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.alloc_zeroed(layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        self.allocations.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
@@ -0,0 +1,898 @@
+use bitvec::prelude::*;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+#[cfg(feature = "stats")]
+use crate::Stats;
+use crate::canonical::{
+    deserialize_code_length_table, deserialize_code_lengths_rle, serialize_code_length_table,
+    serialize_code_lengths_rle,
+};
+use crate::checksum::crc32;
+use crate::decode::DecodeCounter;
+use crate::prepass::pre_passes_in;
+use crate::text::restore_crlf;
+use crate::{
+    ArchiveDescription, ArchiveHeader, ArchiveLayout, CompressError, DecodeTable, DecompressError,
+    EXTENDED_FLAG_LENGTH_TABLE, FLAG_CODE_LENGTHS, FLAG_COMMENT, FLAG_LEAF_WIDTH,
+    FLAG_PAYLOAD_CHECKSUM, FLAG_PRESET_TREE, FLAG_STORED, HuffmanTree, SUPPORTED_FORMAT_VERSIONS,
+};
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// Store a CRC-32 of the input so decompression can verify it
+    pub checksum: bool,
+    /// Stored in the header and shown by `--info`, at most `ArchiveHeader::MAX_COMMENT_LEN`
+    /// bytes
+    pub comment: Option<String>,
+    /// In block mode, code each distinct block once and point repeats at the first copy
+    pub dedup_blocks: bool,
+    /// Threads to compress blocks on in block mode, 0 for one per core
+    pub threads: usize,
+    /// Also store a CRC-32 of the compressed bytes, checked before decoding
+    pub payload_checksum: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            checksum: true,
+            comment: None,
+            dedup_blocks: false,
+            threads: 0,
+            payload_checksum: false,
+        }
+    }
+}
+
+// Bytes encoded between checks of a cancellation flag
+const CANCEL_CHECK_INTERVAL: usize = 64 * 1024;
+// Bytes encoded between feeding the finished part of the archive to the hasher
+#[cfg(feature = "hash")]
+const HASH_INTERVAL: usize = 64 * 1024;
+
+/// How an embedded tree is written, marked in the header by `mark`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TreeEncoding {
+    Shape,
+    /// The shape with leaves of that many bits
+    LeafWidth(u8),
+    CodeLengths,
+    LengthTable,
+}
+
+impl TreeEncoding {
+    pub(crate) fn mark(self, header: &mut ArchiveHeader) {
+        match self {
+            Self::Shape => {}
+            Self::LeafWidth(width) => {
+                header.flags |= FLAG_LEAF_WIDTH;
+                header.leaf_width = Some(width);
+            }
+            Self::CodeLengths => header.flags |= FLAG_CODE_LENGTHS,
+            Self::LengthTable => header.set_extended_flags(EXTENDED_FLAG_LENGTH_TABLE),
+        }
+    }
+}
+
+pub struct HuffmanArchive;
+
+impl HuffmanArchive {
+    /// Stores the tree as its shape or as canonical code lengths, plain or run-length encoded,
+    /// whichever takes fewest bits
+    pub fn compress(data: &[u8], tree: &HuffmanTree) -> BitVec<u8, Msb0> {
+        Self::compress_with_options(data, tree, &Options::default())
+    }
+
+    pub fn compress_with_options(
+        data: &[u8],
+        tree: &HuffmanTree,
+        options: &Options,
+    ) -> BitVec<u8, Msb0> {
+        let mut archive = BitVec::<u8, Msb0>::new();
+        Self::compress_into(data, tree, options, &mut archive);
+        archive
+    }
+
+    /// Appends the archive to `archive`, reusing whatever capacity it has
+    pub(crate) fn compress_into(
+        data: &[u8],
+        tree: &HuffmanTree,
+        options: &Options,
+        archive: &mut BitVec<u8, Msb0>,
+    ) {
+        let (tree_bits, tree_encoding, encoding_table) = Self::smallest_tree_encoding(tree);
+
+        let mut header = Self::new_header(tree_bits.len() as u64, data, options);
+        tree_encoding.mark(&mut header);
+
+        let start = archive.len();
+        header.write(archive);
+
+        Self::write_tree(&header, &tree_bits, archive);
+
+        Self::encode_payload(data, &encoding_table, archive);
+
+        Self::seal_payload_checksum(archive, start);
+    }
+
+    /// Like `compress_with_options`, giving up with `CompressError::Cancelled` soon after
+    /// `cancel` is set from another thread
+    pub fn compress_cancellable(
+        data: &[u8],
+        tree: &HuffmanTree,
+        options: &Options,
+        cancel: &AtomicBool,
+    ) -> Result<BitVec<u8, Msb0>, CompressError> {
+        let (tree_bits, tree_encoding, encoding_table) = Self::smallest_tree_encoding(tree);
+
+        let mut header = Self::new_header(tree_bits.len() as u64, data, options);
+        tree_encoding.mark(&mut header);
+
+        let mut archive = BitVec::<u8, Msb0>::new();
+
+        header.write(&mut archive);
+
+        Self::write_tree(&header, &tree_bits, &mut archive);
+
+        for chunk in data.chunks(CANCEL_CHECK_INTERVAL) {
+            if cancel.load(Ordering::Relaxed) {
+                return Err(CompressError::Cancelled);
+            }
+            Self::encode_payload(chunk, &encoding_table, &mut archive);
+        }
+
+        Self::seal_payload_checksum(&mut archive, 0);
+
+        Ok(archive)
+    }
+
+    /// The tree's shape or the code lengths of the canonical code with the same lengths, as a
+    /// plain table or run-length encoded, whichever is shortest, along with how it is encoded
+    /// and the codes to write the payload with. Ties keep the shape, then the run-length
+    /// encoding.
+    pub(crate) fn smallest_tree_encoding(
+        tree: &HuffmanTree,
+    ) -> (BitVec<u8, Msb0>, TreeEncoding, Vec<BitVec<u8, Msb0>>) {
+        let costs = tree.tree_serialization_costs();
+        let lengths = tree.code_lengths();
+        if costs.smallest() < costs.shape
+            && let Some(canonical) = HuffmanTree::from_code_lengths(&lengths)
+        {
+            let (tree_bits, encoding) = if costs.rle_code_lengths == costs.smallest() {
+                (
+                    serialize_code_lengths_rle(&lengths),
+                    TreeEncoding::CodeLengths,
+                )
+            } else {
+                (
+                    serialize_code_length_table(&lengths),
+                    TreeEncoding::LengthTable,
+                )
+            };
+            return (tree_bits, encoding, canonical.get_encoding_table());
+        }
+        (
+            tree.serialize(),
+            TreeEncoding::Shape,
+            tree.get_encoding_table(),
+        )
+    }
+
+    /// Codes `data` with the canonical code for its optimal code lengths and stores those
+    /// lengths run-length encoded instead of the tree shape, which is smaller for sparse
+    /// alphabets
+    pub fn compress_canonical(data: &[u8], options: &Options) -> BitVec<u8, Msb0> {
+        let lengths = HuffmanTree::build(data).code_lengths();
+        let tree = HuffmanTree::from_code_lengths(&lengths).unwrap();
+        let tree_bits = serialize_code_lengths_rle(&lengths);
+        let encoding_table = tree.get_encoding_table();
+
+        let mut header = Self::new_header(tree_bits.len() as u64, data, options);
+        header.flags |= FLAG_CODE_LENGTHS;
+
+        let mut archive = BitVec::<u8, Msb0>::new();
+
+        header.write(&mut archive);
+
+        Self::write_tree(&header, &tree_bits, &mut archive);
+
+        Self::encode_payload(data, &encoding_table, &mut archive);
+
+        Self::seal_payload_checksum(&mut archive, 0);
+
+        archive
+    }
+
+    /// Like `compress_with_options`, writing each leaf of the tree in `width` bits. Data with a
+    /// small alphabet, e.g. ASCII text at 7 bits, gets a smaller tree this way.
+    pub fn compress_with_leaf_width(
+        data: &[u8],
+        tree: &HuffmanTree,
+        width: u8,
+        options: &Options,
+    ) -> Result<BitVec<u8, Msb0>, CompressError> {
+        let needed = tree.leaf_width();
+        if !(needed..=8).contains(&width) {
+            return Err(CompressError::LeafWidthTooNarrow { width, needed });
+        }
+
+        let tree_bits = tree.serialize_with_leaf_width(width);
+        let encoding_table = tree.get_encoding_table();
+
+        let mut header = Self::new_header(tree_bits.len() as u64, data, options);
+        header.flags |= FLAG_LEAF_WIDTH;
+        header.leaf_width = Some(width);
+
+        let mut archive = BitVec::<u8, Msb0>::new();
+
+        header.write(&mut archive);
+
+        Self::write_tree(&header, &tree_bits, &mut archive);
+
+        Self::encode_payload(data, &encoding_table, &mut archive);
+
+        Self::seal_payload_checksum(&mut archive, 0);
+
+        Ok(archive)
+    }
+
+    /// Like `compress_with_options`, writing into `out` instead of allocating the archive.
+    /// Returns how many bytes of `out` were used.
+    pub fn compress_into_slice(
+        data: &[u8],
+        options: &Options,
+        out: &mut [u8],
+    ) -> Result<usize, CompressError> {
+        let needed = Self::compressed_bit_len(data, options).div_ceil(8);
+        if needed > out.len() {
+            return Err(CompressError::BufferTooSmall {
+                needed,
+                available: out.len(),
+            });
+        }
+
+        let tree = HuffmanTree::build(data);
+        let (tree_bits, tree_encoding, encoding_table) = Self::smallest_tree_encoding(&tree);
+
+        let mut header = Self::new_header(tree_bits.len() as u64, data, options);
+        tree_encoding.mark(&mut header);
+
+        let bits = out[..needed].view_bits_mut::<Msb0>();
+        let mut pos = 0;
+        let mut put = |field: &BitSlice<u8, Msb0>| {
+            bits[pos..pos + field.len()].copy_from_bitslice(field);
+            pos += field.len();
+        };
+
+        header.encode(|field| put(field.view_bits()));
+        put(&tree_bits);
+        for &byte in data {
+            put(&encoding_table[byte as usize]);
+        }
+        bits[pos..].fill(false);
+
+        Self::seal_payload_checksum_bytes(&mut out[..needed]);
+
+        Ok(needed)
+    }
+
+    /// Compresses with the default options and returns the archive with its SHA-256, hashing
+    /// whole bytes as the payload is written rather than reading the archive again. Output is
+    /// stable for a format version, so the hash works as a content address.
+    #[cfg(feature = "hash")]
+    pub fn compress_with_archive_hash(data: &[u8]) -> (Vec<u8>, [u8; 32]) {
+        use sha2::{Digest, Sha256};
+
+        let tree = HuffmanTree::build(data);
+        let (tree_bits, tree_encoding, encoding_table) = Self::smallest_tree_encoding(&tree);
+
+        // The default options have no payload checksum, nothing is patched in after hashing
+        let mut header = Self::new_header(tree_bits.len() as u64, data, &Options::default());
+        tree_encoding.mark(&mut header);
+
+        let mut archive = BitVec::<u8, Msb0>::new();
+
+        header.write(&mut archive);
+
+        Self::write_tree(&header, &tree_bits, &mut archive);
+
+        let mut hasher = Sha256::new();
+        let mut hashed = 0;
+        for chunk in data.chunks(HASH_INTERVAL) {
+            Self::encode_payload(chunk, &encoding_table, &mut archive);
+            let complete = archive.len() / 8;
+            hasher.update(&archive.as_raw_slice()[hashed..complete]);
+            hashed = complete;
+        }
+
+        let archive = archive.into_vec();
+        hasher.update(&archive[hashed..]);
+        (archive, hasher.finalize().into())
+    }
+
+    /// Exact length in bits of what `compress_with_options` would produce, worked out from the
+    /// tree and the symbol frequencies without encoding the payload
+    pub fn compressed_bit_len(data: &[u8], options: &Options) -> usize {
+        let (overhead_bits, payload_bits) = Self::bit_len_parts(data, options);
+        overhead_bits + payload_bits
+    }
+
+    /// Share of the archive `compress_with_options` would produce taken up by the header and
+    /// the tree, from 0 to 1. Close to 1 for tiny inputs, where `store` may be the better fit.
+    pub fn header_overhead_fraction(data: &[u8], options: &Options) -> f64 {
+        let (overhead_bits, payload_bits) = Self::bit_len_parts(data, options);
+        overhead_bits as f64 / (overhead_bits + payload_bits) as f64
+    }
+
+    // Bits of header and tree, and bits of payload
+    fn bit_len_parts(data: &[u8], options: &Options) -> (usize, usize) {
+        let frequency = HuffmanTree::frequencies(data);
+        let tree = HuffmanTree::from_frequencies(&frequency);
+        let costs = tree.tree_serialization_costs();
+        let mut header = Self::new_header(0, &[], options);
+        // The length table is marked in the extended flags, which grow the header
+        if costs.smallest() < costs.shape && costs.rle_code_lengths != costs.smallest() {
+            TreeEncoding::LengthTable.mark(&mut header);
+        }
+
+        let payload_bits: usize = frequency
+            .iter()
+            .zip(tree.code_lengths())
+            .map(|(&freq, length)| freq * length as usize)
+            .sum();
+        let overhead_bits = header.byte_len() * 8 + costs.smallest();
+        (overhead_bits, payload_bits)
+    }
+
+    /// Compresses with the default options, pinned to a format version. Callers that depend on
+    /// byte-identical output (e.g. content-addressed storage) can pass the version they
+    /// certified against and get an error instead of different bytes after an upgrade.
+    pub fn compress_with_format_version(
+        data: &[u8],
+        version: u8,
+    ) -> Result<BitVec<u8, Msb0>, CompressError> {
+        if !SUPPORTED_FORMAT_VERSIONS.contains(&version) {
+            return Err(CompressError::UnsupportedFormatVersion(version));
+        }
+
+        let tree = HuffmanTree::build(data);
+        if version == 1 {
+            return Ok(Self::compress_version_1(data, &tree));
+        }
+        Ok(Self::compress(data, &tree))
+    }
+
+    // Version 1 always stores the tree shape
+    fn compress_version_1(data: &[u8], tree: &HuffmanTree) -> BitVec<u8, Msb0> {
+        let tree_bits = tree.serialize();
+
+        let mut header = Self::new_header(tree_bits.len() as u64, data, &Options::default());
+        header.version = 1;
+
+        let mut archive = BitVec::<u8, Msb0>::new();
+
+        header.write(&mut archive);
+
+        Self::write_tree(&header, &tree_bits, &mut archive);
+
+        Self::encode_payload(data, &tree.get_encoding_table(), &mut archive);
+
+        archive
+    }
+
+    /// Compresses data that may only contain the bytes in `allowed`, e.g. the base64 alphabet,
+    /// failing with `CompressError::SymbolNotInTree` at the first byte outside it. The tree is
+    /// stored with leaves as narrow as the largest byte used allows when that is smallest.
+    pub fn compress_restricted(
+        data: &[u8],
+        allowed: &[u8],
+        options: &Options,
+    ) -> Result<BitVec<u8, Msb0>, CompressError> {
+        let mut is_allowed = [false; 256];
+        for &byte in allowed {
+            is_allowed[byte as usize] = true;
+        }
+        if let Some(offset) = data.iter().position(|&byte| !is_allowed[byte as usize]) {
+            return Err(CompressError::SymbolNotInTree {
+                byte: data[offset],
+                offset,
+            });
+        }
+
+        let tree = HuffmanTree::build(data);
+        let width = tree.leaf_width();
+        let costs = tree.tree_serialization_costs();
+        if tree.serialize_with_leaf_width(width).len() < costs.smallest() {
+            Self::compress_with_leaf_width(data, &tree, width, options)
+        } else {
+            Ok(Self::compress_with_options(data, &tree, options))
+        }
+    }
+
+    /// Compresses without storing the tree, the decoder needs the same tree as a preset
+    pub fn compress_with_tree(
+        data: &[u8],
+        tree: &HuffmanTree,
+        options: &Options,
+    ) -> Result<BitVec<u8, Msb0>, CompressError> {
+        let encoding_table = tree.get_encoding_table();
+
+        if let Some(offset) = data
+            .iter()
+            .position(|&byte| encoding_table[byte as usize].is_empty())
+        {
+            return Err(CompressError::SymbolNotInTree {
+                byte: data[offset],
+                offset,
+            });
+        }
+
+        let mut header = Self::new_header(0, data, options);
+        header.flags |= FLAG_PRESET_TREE;
+
+        let mut archive = BitVec::<u8, Msb0>::new();
+
+        header.write(&mut archive);
+
+        Self::encode_payload(data, &encoding_table, &mut archive);
+
+        Self::seal_payload_checksum(&mut archive, 0);
+
+        Ok(archive)
+    }
+
+    /// Exact length in bits of the payload `compress_with_tree` writes for `data`, without the
+    /// header or the padding after it, so space can be reserved before compressing
+    pub fn payload_bit_len_with_tree(
+        data: &[u8],
+        tree: &HuffmanTree,
+    ) -> Result<usize, CompressError> {
+        let lengths = tree.code_lengths();
+        let mut bits = 0;
+        for (offset, &byte) in data.iter().enumerate() {
+            match lengths[byte as usize] {
+                0 => return Err(CompressError::SymbolNotInTree { byte, offset }),
+                length => bits += length as usize,
+            }
+        }
+        Ok(bits)
+    }
+
+    /// Wraps `data` in an archive without coding it
+    pub fn store(data: &[u8], options: &Options) -> BitVec<u8, Msb0> {
+        let mut header = Self::new_header(0, data, options);
+        header.flags |= FLAG_STORED;
+
+        let mut archive = BitVec::<u8, Msb0>::new();
+
+        header.write(&mut archive);
+
+        archive.extend_from_bitslice(data.view_bits::<Msb0>());
+
+        Self::seal_payload_checksum(&mut archive, 0);
+
+        archive
+    }
+
+    /// Header for an archive of `data`, with the checksum and comment `options` ask for
+    pub(crate) fn new_header(tree_len: u64, data: &[u8], options: &Options) -> ArchiveHeader {
+        let checksum = options.checksum.then(|| crc32(data));
+        let mut header = ArchiveHeader::new(tree_len, data.len() as u64, checksum);
+        if let Some(comment) = &options.comment {
+            assert!(
+                comment.len() <= ArchiveHeader::MAX_COMMENT_LEN,
+                "comment is longer than {} bytes",
+                ArchiveHeader::MAX_COMMENT_LEN
+            );
+            header.flags |= FLAG_COMMENT;
+            header.comment = Some(comment.clone());
+        }
+        if options.payload_checksum {
+            // Filled in by `seal_payload_checksum` once the rest is written
+            header.flags |= FLAG_PAYLOAD_CHECKSUM;
+            header.payload_len = Some(0);
+            header.payload_checksum = Some(0);
+        }
+        header
+    }
+
+    /// Fills in the payload length and CRC of the archive starting `start` bits into
+    /// `archive`, if its header asks for them, once everything after the header is written
+    pub(crate) fn seal_payload_checksum(archive: &mut BitVec<u8, Msb0>, start: usize) {
+        debug_assert_eq!(start % 8, 0);
+        archive.set_uninitialized(false);
+        Self::seal_payload_checksum_bytes(&mut archive.as_raw_mut_slice()[start / 8..]);
+    }
+
+    pub(crate) fn seal_payload_checksum_bytes(archive_bytes: &mut [u8]) {
+        let mut header = ArchiveHeader::parse(archive_bytes).unwrap();
+        if !header.has_payload_checksum() {
+            return;
+        }
+
+        let payload = &archive_bytes[header.byte_len()..];
+        header.payload_len = Some(payload.len() as u64);
+        header.payload_checksum = Some(crc32(payload));
+
+        let mut pos = 0;
+        header.encode(|field| {
+            archive_bytes[pos..pos + field.len()].copy_from_slice(field);
+            pos += field.len();
+        });
+    }
+
+    /// Appends the tree after the header and index, checking it ends where `tree_len` says
+    pub(crate) fn write_tree(
+        header: &ArchiveHeader,
+        tree_bits: &BitSlice<u8, Msb0>,
+        archive: &mut BitVec<u8, Msb0>,
+    ) {
+        archive.extend_from_bitslice(tree_bits);
+        // Decoders skip tree_len bits past the body offset to find the payload
+        debug_assert_eq!(
+            archive.len(),
+            header.body_offset() * 8 + header.tree_len as usize,
+            "serialized tree does not match tree_len"
+        );
+    }
+
+    pub(crate) fn encode_payload(
+        data: &[u8],
+        encoding_table: &[BitVec<u8, Msb0>],
+        archive: &mut BitVec<u8, Msb0>,
+    ) {
+        for &byte in data {
+            archive.extend_from_bitslice(&encoding_table[byte as usize]);
+        }
+    }
+
+    pub fn describe(archive_bytes: &[u8]) -> Result<ArchiveDescription, DecompressError> {
+        Ok(ArchiveHeader::parse(archive_bytes)?.describe())
+    }
+
+    /// Length of the original data, read from the header without decoding anything
+    pub fn decoded_len(archive_bytes: &[u8]) -> Result<u64, DecompressError> {
+        Ok(ArchiveHeader::parse(archive_bytes)?.data_len)
+    }
+
+    /// Reads the tree an archive was coded with, without decoding the payload
+    pub fn extract_tree(archive_bytes: &[u8]) -> Result<HuffmanTree, DecompressError> {
+        let header = ArchiveHeader::parse(archive_bytes)?;
+        if header.is_stored() || header.has_preset_tree() || header.has_blocks() {
+            return Err(DecompressError::NoEmbeddedTree);
+        }
+
+        let tree_start = header.body_offset() * 8;
+        let tree_bits = archive_bytes
+            .view_bits::<Msb0>()
+            .get(tree_start..tree_start + header.tree_len as usize)
+            .ok_or(DecompressError::TruncatedTree)?;
+        Self::read_tree(&header, &mut tree_bits.iter().by_vals())
+    }
+
+    /// Bit offsets of the tree and the payload, found by parsing the header and reading the tree
+    pub fn layout(archive_bytes: &[u8]) -> Result<ArchiveLayout, DecompressError> {
+        let header = ArchiveHeader::parse(archive_bytes)?;
+        let tree_start = header.body_offset() * 8;
+        if header.is_stored() || header.has_preset_tree() || header.has_blocks() {
+            return Ok(ArchiveLayout {
+                tree_start,
+                payload_start: tree_start,
+            });
+        }
+
+        let bits = archive_bytes
+            .view_bits::<Msb0>()
+            .get(tree_start..)
+            .ok_or(DecompressError::TruncatedTree)?;
+        let mut bit_iter = bits.iter().by_vals();
+        Self::read_tree(&header, &mut bit_iter)?;
+        Ok(ArchiveLayout {
+            tree_start,
+            payload_start: tree_start + bits.len() - bit_iter.len(),
+        })
+    }
+
+    /// `HuffmanTree::codebook_fingerprint` of the archive's tree, without decoding the payload.
+    /// Archives coded with the same code lengths share a fingerprint. A dictionary archive
+    /// gives the fingerprint in its header, so one that leaves the dictionary out has one too.
+    pub fn codebook_fingerprint(archive_bytes: &[u8]) -> Result<u64, DecompressError> {
+        if let Some(fingerprint) = ArchiveHeader::parse(archive_bytes)?.dictionary {
+            return Ok(fingerprint);
+        }
+        Ok(Self::extract_tree(archive_bytes)?.codebook_fingerprint())
+    }
+
+    /// Reads the embedded tree in whichever encoding the header says it was written in
+    pub(crate) fn read_tree(
+        header: &ArchiveHeader,
+        bit_iter: &mut impl Iterator<Item = bool>,
+    ) -> Result<HuffmanTree, DecompressError> {
+        if header.has_code_lengths() || header.has_length_table() {
+            let lengths = if header.has_code_lengths() {
+                deserialize_code_lengths_rle(bit_iter)?
+            } else {
+                deserialize_code_length_table(bit_iter)?
+            };
+            HuffmanTree::from_code_lengths(&lengths).ok_or(DecompressError::InvalidTree)
+        } else {
+            HuffmanTree::deserialize_shape_with_leaf_width(bit_iter, header.leaf_bits())
+        }
+    }
+
+    pub fn decompress(archive_bytes: &[u8]) -> Result<Vec<u8>, DecompressError> {
+        Self::decompress_with_table(archive_bytes, None)
+    }
+
+    /// Decompresses an archive of text, failing if the decoded bytes are not valid UTF-8
+    pub fn decompress_to_string(archive_bytes: &[u8]) -> Result<String, DecompressError> {
+        String::from_utf8(Self::decompress(archive_bytes)?).map_err(DecompressError::InvalidUtf8)
+    }
+
+    /// Decodes into the start of `out` without allocating the output, returning the decoded
+    /// length. The length is read from the header first, so a buffer that is too small is
+    /// rejected before any decoding.
+    pub fn decompress_into_slice(
+        archive_bytes: &[u8],
+        out: &mut [u8],
+    ) -> Result<usize, DecompressError> {
+        let header = ArchiveHeader::parse(archive_bytes)?;
+        let needed = header.data_len as usize;
+        if needed > out.len() {
+            return Err(DecompressError::BufferTooSmall {
+                needed,
+                available: out.len(),
+            });
+        }
+        // Also bounds the first pass of a double-coded archive
+        Self::check_data_len(archive_bytes, &header)?;
+
+        Self::decompress_frame_into(archive_bytes, &header, None, &mut (), &mut out[..needed])?;
+        Ok(needed)
+    }
+
+    /// Decompresses into memory owned by `arena`, so callers handling many requests can free
+    /// every output at once instead of going through the global allocator for each
+    #[cfg(feature = "arena")]
+    pub fn decompress_in<'a>(
+        archive_bytes: &[u8],
+        arena: &'a bumpalo::Bump,
+    ) -> Result<&'a [u8], DecompressError> {
+        let header = ArchiveHeader::parse(archive_bytes)?;
+        Self::check_data_len(archive_bytes, &header)?;
+        let output = arena.alloc_slice_fill_copy(header.data_len as usize, 0);
+        Self::decompress_frame_into(archive_bytes, &header, None, &mut (), output)?;
+        Ok(output)
+    }
+
+    /// Decodes archives laid back to back, as written by `FrameEncoder`
+    pub fn decompress_frames(mut bytes: &[u8]) -> Result<Vec<u8>, DecompressError> {
+        let mut output = Vec::new();
+
+        while !bytes.is_empty() {
+            let (frame, consumed) = Self::decompress_frame(bytes, None, &mut ())?;
+            output.extend_from_slice(&frame);
+            bytes = &bytes[consumed..];
+        }
+
+        Ok(output)
+    }
+
+    /// Decodes only the frame at the start of `bytes`, also returning how many bytes it took
+    /// up, so frames can be decoded as they arrive. A frame that has not fully arrived yet fails
+    /// with `TooShort`, `TruncatedTree` or `TruncatedPayload`.
+    pub fn decompress_first_frame(bytes: &[u8]) -> Result<(Vec<u8>, usize), DecompressError> {
+        Self::decompress_frame(bytes, None, &mut ())
+    }
+
+    /// Decodes every frame and checks its checksum, keeping none of the output
+    pub fn validate(mut bytes: &[u8]) -> Result<(), DecompressError> {
+        while !bytes.is_empty() {
+            let (_, consumed) = Self::decompress_frame(bytes, None, &mut ())?;
+            bytes = &bytes[consumed..];
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn decompress_with_table(
+        archive_bytes: &[u8],
+        preset: Option<&DecodeTable>,
+    ) -> Result<Vec<u8>, DecompressError> {
+        Ok(Self::decompress_frame(archive_bytes, preset, &mut ())?.0)
+    }
+
+    /// Decompresses while counting the work the decoder does
+    #[cfg(feature = "stats")]
+    pub fn decompress_with_stats(
+        archive_bytes: &[u8],
+    ) -> Result<(Vec<u8>, Stats), DecompressError> {
+        let mut stats = Stats::default();
+        let (output, _) = Self::decompress_frame(archive_bytes, None, &mut stats)?;
+        Ok((output, stats))
+    }
+
+    /// Decodes the archive at the start of `archive_bytes`, also returning how many bytes it
+    /// took up so that a following frame can be found
+    pub(crate) fn decompress_frame(
+        archive_bytes: &[u8],
+        preset: Option<&DecodeTable>,
+        counter: &mut impl DecodeCounter,
+    ) -> Result<(Vec<u8>, usize), DecompressError> {
+        let header = ArchiveHeader::parse(archive_bytes)?;
+        Self::check_data_len(archive_bytes, &header)?;
+        let mut output = vec![0; header.data_len as usize];
+        let consumed =
+            Self::decompress_frame_into(archive_bytes, &header, preset, counter, &mut output)?;
+        Ok((output, consumed))
+    }
+
+    /// Fails when `data_len` is more than the archive could hold, so that a corrupt header is
+    /// caught before its length is allocated. A stored archive holds a byte per byte and every
+    /// code is at least a bit long.
+    pub(crate) fn check_data_len(
+        archive_bytes: &[u8],
+        header: &ArchiveHeader,
+    ) -> Result<(), DecompressError> {
+        if header.has_blocks() {
+            return Self::check_blocks_len(archive_bytes, header);
+        }
+
+        let crlf_count = header.crlf_count.unwrap_or(0);
+        let coded_len = header
+            .data_len
+            .checked_sub(crlf_count)
+            .ok_or(DecompressError::InvalidLineEndings)?;
+        // Every CR goes back in before an LF that was coded
+        if crlf_count > coded_len {
+            return Err(DecompressError::InvalidLineEndings);
+        }
+
+        let body_len = archive_bytes.len().saturating_sub(header.body_offset()) as u64;
+        let capacity = if header.is_stored() {
+            body_len
+        } else {
+            match header.first_pass_len {
+                Some(first_pass_len) if first_pass_len > body_len * 8 => {
+                    return Err(DecompressError::TruncatedPayload);
+                }
+                Some(first_pass_len) => first_pass_len * 8,
+                None => body_len * 8,
+            }
+        };
+        if coded_len > capacity {
+            return Err(DecompressError::TruncatedPayload);
+        }
+
+        Ok(())
+    }
+
+    /// Decodes into `output`, which must be exactly `data_len` bytes long
+    pub(crate) fn decompress_frame_into(
+        archive_bytes: &[u8],
+        header: &ArchiveHeader,
+        preset: Option<&DecodeTable>,
+        counter: &mut impl DecodeCounter,
+        output: &mut [u8],
+    ) -> Result<usize, DecompressError> {
+        debug_assert_eq!(output.len() as u64, header.data_len);
+
+        Self::verify_payload_checksum(archive_bytes, header)?;
+
+        if header.has_blocks() {
+            return Self::decompress_blocks_into(archive_bytes, header, counter, output);
+        }
+
+        // The CRs of a `FLAG_CRLF` archive are not coded, they go back in at the end
+        let coded_len = output
+            .len()
+            .checked_sub(header.crlf_count.unwrap_or(0) as usize)
+            .ok_or(DecompressError::InvalidLineEndings)?;
+
+        if header.is_stored() {
+            let end = header.body_offset() + coded_len;
+            let stored = archive_bytes
+                .get(header.body_offset()..end)
+                .ok_or(DecompressError::TruncatedPayload)?;
+            output[..coded_len].copy_from_slice(stored);
+            counter.bits(coded_len * 8);
+            Self::undo_transforms(header, output, coded_len)?;
+            Self::verify_checksum(header, output)?;
+            return Ok(end);
+        }
+
+        let bits = archive_bytes.view_bits::<Msb0>();
+        let mut bit_iter = bits
+            .get(header.body_offset() * 8..)
+            .ok_or(DecompressError::TooShort)?
+            .iter()
+            .by_vals();
+
+        let embedded;
+        let table = if header.has_preset_tree() {
+            preset.ok_or(match header.dictionary {
+                Some(fingerprint) => DecompressError::MissingDictionary(fingerprint),
+                None => DecompressError::MissingPresetTree,
+            })?
+        } else {
+            let tree = Self::read_tree(header, &mut bit_iter)?;
+            embedded = DecodeTable::new(&tree)?;
+            &embedded
+        };
+
+        let payload_start = bits.len() - bit_iter.len();
+        let payload_end = match header.first_pass_len {
+            Some(first_pass_len) => {
+                let mut first_pass = vec![0; first_pass_len as usize];
+                let end = table.decode(archive_bytes, payload_start, &mut first_pass, counter)?;
+                Self::decode_first_pass(&first_pass, &mut output[..coded_len], counter)?;
+                end
+            }
+            None => table.decode(
+                archive_bytes,
+                payload_start,
+                &mut output[..coded_len],
+                counter,
+            )?,
+        };
+        counter.bits(payload_end - payload_start);
+
+        let consumed = payload_end.div_ceil(8);
+
+        Self::undo_transforms(header, output, coded_len)?;
+        Self::verify_checksum(header, output)?;
+
+        Ok(consumed)
+    }
+
+    // Undoes the pre-passes over the `coded_len` decoded bytes, then restores CRLF line
+    // endings, which were dropped before the pre-passes ran
+    fn undo_transforms(
+        header: &ArchiveHeader,
+        output: &mut [u8],
+        coded_len: usize,
+    ) -> Result<(), DecompressError> {
+        for pass in pre_passes_in(header.pre_passes.unwrap_or(0)).rev() {
+            pass.undo(&mut output[..coded_len]);
+        }
+        if header.has_crlf() {
+            restore_crlf(output, coded_len)?;
+        }
+        Ok(())
+    }
+
+    // Checks the CRC of the compressed bytes, before any decoding
+    fn verify_payload_checksum(
+        archive_bytes: &[u8],
+        header: &ArchiveHeader,
+    ) -> Result<(), DecompressError> {
+        if let (Some(len), Some(expected)) = (header.payload_len, header.payload_checksum) {
+            let start = header.byte_len();
+            let payload = archive_bytes
+                .get(start..start + len as usize)
+                .ok_or(DecompressError::TruncatedPayload)?;
+            let actual = crc32(payload);
+            if expected != actual {
+                return Err(DecompressError::PayloadChecksumMismatch { expected, actual });
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn verify_checksum(
+        header: &ArchiveHeader,
+        output: &[u8],
+    ) -> Result<(), DecompressError> {
+        // Archives written without a checksum are trusted as-is
+        if header.has_checksum() {
+            let expected = header.checksum.unwrap();
+            let actual = crc32(output);
+            if expected != actual {
+                return Err(DecompressError::ChecksumMismatch { expected, actual });
+            }
+        }
+
+        Ok(())
+    }
+}
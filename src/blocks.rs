@@ -0,0 +1,340 @@
+use bitvec::prelude::*;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::thread;
+
+use crate::decode::DecodeCounter;
+use crate::header::INDEX_ENTRY_LEN;
+use crate::{ArchiveHeader, DecompressError, FLAG_BLOCKS, HuffmanArchive, Options, encode_frame};
+
+// Blocks are only checked through the checksum over the whole input
+const BLOCK_OPTIONS: Options = Options {
+    checksum: false,
+    comment: None,
+    dedup_blocks: false,
+    threads: 1,
+    payload_checksum: false,
+};
+
+// Index entries that may share one frame. Without a cap, an archive pointing every entry at
+// one large frame could claim a `data_len` that grows with the square of its size.
+pub(crate) const MAX_FRAME_REUSE: usize = 1 << 12;
+
+impl HuffmanArchive {
+    /// Splits `data` into blocks of `block_size` bytes and compresses each with its own tree,
+    /// so the code can follow data whose byte distribution changes along the way
+    pub fn compress_blocks(data: &[u8], block_size: usize, options: &Options) -> BitVec<u8, Msb0> {
+        assert!(block_size > 0, "block size must be at least one byte");
+        Self::compress_blocks_at(
+            data,
+            &fixed_size_boundaries(data.len(), block_size),
+            options,
+        )
+    }
+
+    /// Like `compress_blocks`, with a block starting at every offset in `boundaries`. With
+    /// `options.dedup_blocks`, a block identical to an earlier one reuses its frame through the
+    /// index instead of being coded again, for up to `MAX_FRAME_REUSE` blocks per frame.
+    pub fn compress_blocks_at(
+        data: &[u8],
+        boundaries: &[usize],
+        options: &Options,
+    ) -> BitVec<u8, Msb0> {
+        Self::compress_block_ranges(
+            data,
+            block_ranges(data.len(), boundaries),
+            FLAG_BLOCKS,
+            options,
+        )
+    }
+
+    /// Codes every range of `data` as a block, ranges must be back to back from the start of
+    /// `data` to its end
+    pub(crate) fn compress_block_ranges(
+        data: &[u8],
+        ranges: Vec<Range<usize>>,
+        flags: u16,
+        options: &Options,
+    ) -> BitVec<u8, Msb0> {
+        // The frame each block is coded in, and the blocks that get a frame of their own
+        let mut frame_of = Vec::with_capacity(ranges.len());
+        let mut coded = Vec::new();
+        let mut first_copies = HashMap::new();
+        for range in &ranges {
+            let block = &data[range.clone()];
+            let frame = match first_copies.get_mut(block) {
+                Some((frame, uses)) if *uses < MAX_FRAME_REUSE => {
+                    *uses += 1;
+                    *frame
+                }
+                _ => {
+                    coded.push(block);
+                    if options.dedup_blocks {
+                        first_copies.insert(block, (coded.len() - 1, 1));
+                    }
+                    coded.len() - 1
+                }
+            };
+            frame_of.push(frame);
+        }
+
+        let mut frame_offsets = Vec::with_capacity(coded.len());
+        let mut body = Vec::new();
+        for frame in encode_frames(&coded, options.threads) {
+            frame_offsets.push(body.len());
+            body.extend_from_slice(&frame);
+        }
+
+        let mut index = BitVec::<u8, Msb0>::new();
+        for (range, frame) in ranges.iter().zip(frame_of) {
+            let offset = frame_offsets[frame] as u64;
+            index.extend_from_bitslice(offset.to_be_bytes().view_bits::<Msb0>());
+            index.extend_from_bitslice((range.len() as u64).to_be_bytes().view_bits::<Msb0>());
+        }
+
+        let mut header = Self::new_header(0, data, options);
+        header.flags |= flags;
+        header.entry_count = Some(ranges.len() as u64);
+
+        let mut archive = BitVec::<u8, Msb0>::new();
+
+        header.write(&mut archive);
+
+        archive.extend_from_bitslice(&index);
+        archive.extend_from_bitslice(body.view_bits::<Msb0>());
+
+        Self::seal_payload_checksum(&mut archive, 0);
+
+        archive
+    }
+
+    /// Estimated compressed size in bits of every block `compress_blocks_at` would make for
+    /// these boundaries, so candidate layouts can be compared without encoding them
+    pub fn estimate_blocks(data: &[u8], boundaries: &[usize]) -> Vec<usize> {
+        block_ranges(data.len(), boundaries)
+            .into_iter()
+            .map(|range| Self::compressed_bit_len(&data[range], &BLOCK_OPTIONS))
+            .collect()
+    }
+
+    /// The candidate block size whose `compress_blocks` archive `estimate_blocks` expects to be
+    /// smallest, counting the index as well. Ties go to the earlier candidate.
+    pub fn tune_block_size(data: &[u8], candidates: &[usize]) -> usize {
+        assert!(
+            candidates.iter().all(|&size| size > 0),
+            "block size must be at least one byte"
+        );
+        candidates
+            .iter()
+            .copied()
+            .min_by_key(|&block_size| {
+                let boundaries = fixed_size_boundaries(data.len(), block_size);
+                let blocks = Self::estimate_blocks(data, &boundaries);
+                blocks.len() * INDEX_ENTRY_LEN * 8 + blocks.iter().sum::<usize>()
+            })
+            .expect("no candidate block sizes")
+    }
+
+    /// Decodes `start..end` of the original data. In a block archive only the blocks
+    /// overlapping the range are decoded, which skips the checksum over the whole input.
+    /// Other archives are decoded in full and sliced.
+    pub fn decompress_range(
+        archive_bytes: &[u8],
+        start: usize,
+        end: usize,
+    ) -> Result<Vec<u8>, DecompressError> {
+        let header = ArchiveHeader::parse(archive_bytes)?;
+        let len = header.data_len as usize;
+        if start > end || end > len {
+            return Err(DecompressError::RangeOutOfBounds { start, end, len });
+        }
+
+        if !header.has_blocks() {
+            return Ok(Self::decompress(archive_bytes)?[start..end].to_vec());
+        }
+
+        Self::check_blocks_len(archive_bytes, &header)?;
+
+        let mut output = Vec::with_capacity(end - start);
+        let mut block_start = 0;
+        for block in 0..header.entry_count.unwrap_or(0) as usize {
+            let (offset, decoded_len) = header.index_entry(archive_bytes, block)?;
+            let block_end = block_start + decoded_len;
+
+            if block_end > start && block_start < end {
+                let mut decoded = vec![0; decoded_len];
+                Self::decompress_block_into(archive_bytes, &header, offset, &mut (), &mut decoded)?;
+                output.extend_from_slice(
+                    &decoded[start.saturating_sub(block_start)..end.min(block_end) - block_start],
+                );
+            }
+
+            if block_end >= end {
+                break;
+            }
+            block_start = block_end;
+        }
+
+        if output.len() != end - start {
+            return Err(DecompressError::InvalidBlockIndex);
+        }
+
+        Ok(output)
+    }
+
+    // Checks every block against its frame and the blocks together against `data_len`. Each
+    // frame is checked once, against the bytes up to the next one, and may be shared by at most
+    // `MAX_FRAME_REUSE` blocks, so the total stays in proportion to the archive.
+    pub(crate) fn check_blocks_len(
+        archive_bytes: &[u8],
+        header: &ArchiveHeader,
+    ) -> Result<(), DecompressError> {
+        let entries = (0..header.entry_count.unwrap_or(0) as usize)
+            .map(|block| header.index_entry(archive_bytes, block))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut offsets: Vec<usize> = entries.iter().map(|&(offset, _)| offset).collect();
+        offsets.sort_unstable();
+        offsets.dedup();
+        // Decoded length of the frame at each offset and the blocks sharing it so far
+        let mut frames = HashMap::with_capacity(offsets.len());
+        for (i, &offset) in offsets.iter().enumerate() {
+            let (frame, frame_header) = Self::block_frame(archive_bytes, header, offset)?;
+            let frame_len = offsets.get(i + 1).map_or(frame.len(), |next| next - offset);
+            Self::check_data_len(&frame[..frame_len.min(frame.len())], &frame_header)?;
+            frames.insert(offset, (frame_header.data_len, 0));
+        }
+
+        let mut total: u64 = 0;
+        for (offset, decoded_len) in entries {
+            let (frame_data_len, uses) = frames.get_mut(&offset).unwrap();
+            *uses += 1;
+            if *frame_data_len != decoded_len as u64 || *uses > MAX_FRAME_REUSE {
+                return Err(DecompressError::InvalidBlockIndex);
+            }
+            total = total
+                .checked_add(decoded_len as u64)
+                .ok_or(DecompressError::InvalidBlockIndex)?;
+        }
+
+        if total != header.data_len {
+            return Err(DecompressError::InvalidBlockIndex);
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn decompress_blocks_into(
+        archive_bytes: &[u8],
+        header: &ArchiveHeader,
+        counter: &mut impl DecodeCounter,
+        output: &mut [u8],
+    ) -> Result<usize, DecompressError> {
+        let mut consumed = header.body_offset();
+        let mut written = 0;
+
+        for block in 0..header.entry_count.unwrap_or(0) as usize {
+            let (offset, decoded_len) = header.index_entry(archive_bytes, block)?;
+            let block_output = output
+                .get_mut(written..written + decoded_len)
+                .ok_or(DecompressError::InvalidBlockIndex)?;
+
+            let frame_end =
+                Self::decompress_block_into(archive_bytes, header, offset, counter, block_output)?;
+            consumed = consumed.max(frame_end);
+            written += decoded_len;
+        }
+
+        if written != output.len() {
+            return Err(DecompressError::InvalidBlockIndex);
+        }
+
+        Self::verify_checksum(header, output)?;
+
+        Ok(consumed)
+    }
+
+    // Decodes the frame `offset` bytes into the body, returning where in the archive it ends
+    fn decompress_block_into(
+        archive_bytes: &[u8],
+        header: &ArchiveHeader,
+        offset: usize,
+        counter: &mut impl DecodeCounter,
+        output: &mut [u8],
+    ) -> Result<usize, DecompressError> {
+        let (frame, frame_header) = Self::block_frame(archive_bytes, header, offset)?;
+        if frame_header.data_len != output.len() as u64 {
+            return Err(DecompressError::InvalidBlockIndex);
+        }
+
+        let frame_len = Self::decompress_frame_into(frame, &frame_header, None, counter, output)?;
+        Ok(header.body_offset() + offset + frame_len)
+    }
+
+    // The frame `offset` bytes into the body with its header
+    fn block_frame<'a>(
+        archive_bytes: &'a [u8],
+        header: &ArchiveHeader,
+        offset: usize,
+    ) -> Result<(&'a [u8], ArchiveHeader), DecompressError> {
+        let frame = archive_bytes
+            .get(header.body_offset().saturating_add(offset)..)
+            .ok_or(DecompressError::TruncatedPayload)?;
+        let frame_header = ArchiveHeader::parse(frame)?;
+        // Blocks are plain frames, one nested in another could nest without end
+        if frame_header.has_index() {
+            return Err(DecompressError::InvalidBlockIndex);
+        }
+        Ok((frame, frame_header))
+    }
+}
+
+// Encodes each block as a frame, on up to `threads` threads (one per core for 0). Every thread
+// takes a run of consecutive blocks, so the frames come back in order whatever the count.
+fn encode_frames(blocks: &[&[u8]], threads: usize) -> Vec<Vec<u8>> {
+    let threads = match threads {
+        0 => thread::available_parallelism().map_or(1, |cores| cores.get()),
+        threads => threads,
+    };
+    if threads <= 1 || blocks.len() <= 1 {
+        return blocks
+            .iter()
+            .map(|block| encode_frame(block, &BLOCK_OPTIONS))
+            .collect();
+    }
+
+    let per_thread = blocks.len().div_ceil(threads);
+    thread::scope(|scope| {
+        let workers: Vec<_> = blocks
+            .chunks(per_thread)
+            .map(|run| scope.spawn(move || encode_frames(run, 1)))
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap())
+            .collect()
+    })
+}
+
+// Block start offsets every `block_size` bytes, leaving out the one at 0
+fn fixed_size_boundaries(len: usize, block_size: usize) -> Vec<usize> {
+    (block_size..len).step_by(block_size).collect()
+}
+
+// Turns block start offsets into the ranges of the non-empty blocks
+fn block_ranges(len: usize, boundaries: &[usize]) -> Vec<Range<usize>> {
+    assert!(
+        boundaries.is_sorted() && boundaries.last().is_none_or(|&last| last <= len),
+        "block boundaries must be sorted and within the data"
+    );
+
+    let mut ranges = Vec::with_capacity(boundaries.len() + 1);
+    let mut start = 0;
+    for &end in boundaries.iter().chain([&len]) {
+        if end > start {
+            ranges.push(start..end);
+            start = end;
+        }
+    }
+    ranges
+}
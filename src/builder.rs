@@ -0,0 +1,104 @@
+use bitvec::prelude::*;
+
+use crate::{ArchiveHeader, HuffmanTree};
+
+/// Assembles an archive from explicit parts, for tests that need precisely malformed input.
+/// Nothing is checked, the header is written as given even when it contradicts the body.
+#[derive(Debug, Clone)]
+pub struct ArchiveBuilder {
+    header: ArchiveHeader,
+    tree_bits: BitVec<u8, Msb0>,
+    payload_bits: BitVec<u8, Msb0>,
+}
+
+impl ArchiveBuilder {
+    /// An empty single-tree archive without a checksum
+    pub fn new() -> Self {
+        Self {
+            header: ArchiveHeader::new(0, 0, None),
+            tree_bits: BitVec::new(),
+            payload_bits: BitVec::new(),
+        }
+    }
+
+    pub fn version(mut self, version: u8) -> Self {
+        self.header.version = version;
+        self
+    }
+
+    /// Replaces the flags, optional fields are still written whenever they are set
+    pub fn flags(mut self, flags: u16) -> Self {
+        self.header.flags = flags;
+        self
+    }
+
+    pub fn tree_len(mut self, tree_len: u64) -> Self {
+        self.header.tree_len = tree_len;
+        self
+    }
+
+    pub fn data_len(mut self, data_len: u64) -> Self {
+        self.header.data_len = data_len;
+        self
+    }
+
+    pub fn checksum(mut self, checksum: Option<u32>) -> Self {
+        self.header.checksum = checksum;
+        self
+    }
+
+    pub fn entry_count(mut self, entry_count: Option<u64>) -> Self {
+        self.header.entry_count = entry_count;
+        self
+    }
+
+    pub fn leaf_width(mut self, leaf_width: Option<u8>) -> Self {
+        self.header.leaf_width = leaf_width;
+        self
+    }
+
+    pub fn pre_passes(mut self, pre_passes: Option<u8>) -> Self {
+        self.header.pre_passes = pre_passes;
+        self
+    }
+
+    pub fn extended_flags(mut self, extended_flags: Option<u16>) -> Self {
+        self.header.extended_flags = extended_flags;
+        self
+    }
+
+    /// Serializes `tree` as the tree and sets `tree_len` to match
+    pub fn tree(self, tree: &HuffmanTree) -> Self {
+        let tree_bits = tree.serialize();
+        let tree_len = tree_bits.len() as u64;
+        self.tree_bits(tree_bits).tree_len(tree_len)
+    }
+
+    /// Raw bits for the tree, `tree_len` is left alone
+    pub fn tree_bits(mut self, tree_bits: BitVec<u8, Msb0>) -> Self {
+        self.tree_bits = tree_bits;
+        self
+    }
+
+    pub fn payload_bits(mut self, payload_bits: BitVec<u8, Msb0>) -> Self {
+        self.payload_bits = payload_bits;
+        self
+    }
+
+    pub fn build(&self) -> Vec<u8> {
+        let mut archive = BitVec::<u8, Msb0>::new();
+
+        self.header.write(&mut archive);
+
+        archive.extend_from_bitslice(&self.tree_bits);
+        archive.extend_from_bitslice(&self.payload_bits);
+
+        archive.into_vec()
+    }
+}
+
+impl Default for ArchiveBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
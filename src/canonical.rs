@@ -0,0 +1,198 @@
+use bitvec::prelude::*;
+
+use crate::DecompressError;
+
+// Token tags of the run-length encoded code lengths
+const RLE_ZEROS: u8 = 0b00;
+const RLE_LENGTH: u8 = 0b01;
+const RLE_REPEAT: u8 = 0b10;
+
+/// Computes optimal code lengths straight from a frequency table of any alphabet size, using
+/// the in-place algorithm of Moffat and Katajainen on a sorted copy of the frequencies instead
+/// of allocating tree nodes. Absent symbols get length 0, and a lone symbol gets length 1 to
+/// match the dummy leaf `HuffmanTree::build` pairs it with.
+pub fn code_lengths_from_frequencies(freqs: &[usize]) -> Vec<u8> {
+    let mut lengths = vec![0u8; freqs.len()];
+
+    let mut symbols: Vec<usize> = (0..freqs.len()).filter(|&s| freqs[s] != 0).collect();
+    symbols.sort_by_key(|&s| freqs[s]);
+
+    match symbols.len() {
+        0 => return lengths,
+        1 => {
+            lengths[symbols[0]] = 1;
+            return lengths;
+        }
+        _ => {}
+    }
+
+    let mut a: Vec<usize> = symbols.iter().map(|&s| freqs[s]).collect();
+    minimum_redundancy(&mut a);
+
+    for (&symbol, &length) in symbols.iter().zip(&a) {
+        lengths[symbol] = length as u8;
+    }
+    lengths
+}
+
+// Turns ascending frequencies into code lengths in place, `a` must have at least two entries
+fn minimum_redundancy(a: &mut [usize]) {
+    let n = a.len();
+
+    // Phase 1: build the tree, leaving parent pointers in the slots of merged nodes
+    a[0] += a[1];
+    let mut root = 0;
+    let mut leaf = 2;
+    for next in 1..n - 1 {
+        if leaf >= n || a[root] < a[leaf] {
+            a[next] = a[root];
+            a[root] = next;
+            root += 1;
+        } else {
+            a[next] = a[leaf];
+            leaf += 1;
+        }
+
+        if leaf >= n || (root < next && a[root] < a[leaf]) {
+            a[next] += a[root];
+            a[root] = next;
+            root += 1;
+        } else {
+            a[next] += a[leaf];
+            leaf += 1;
+        }
+    }
+
+    // Phase 2: turn parent pointers into internal node depths
+    a[n - 2] = 0;
+    for next in (0..n.saturating_sub(2)).rev() {
+        a[next] = a[a[next]] + 1;
+    }
+
+    // Phase 3: turn internal node depths into leaf depths
+    let mut available = 1;
+    let mut used = 0;
+    let mut depth = 0;
+    let mut root = n as isize - 2;
+    let mut next = n as isize - 1;
+    while available > 0 {
+        while root >= 0 && a[root as usize] == depth {
+            used += 1;
+            root -= 1;
+        }
+        while available > used {
+            a[next as usize] = depth;
+            next -= 1;
+            available -= 1;
+        }
+        available = 2 * used;
+        depth += 1;
+        used = 0;
+    }
+}
+
+/// Assigns canonical codes: shorter codes first and symbols of equal length in increasing
+/// order, each code being the previous one plus one. Symbols with length 0 get an empty code.
+pub fn canonical_codes(lengths: &[u8]) -> Vec<BitVec<u8, Msb0>> {
+    let mut codes = vec![BitVec::<u8, Msb0>::new(); lengths.len()];
+
+    let mut symbols: Vec<usize> = (0..lengths.len()).filter(|&s| lengths[s] != 0).collect();
+    symbols.sort_by_key(|&s| lengths[s]);
+
+    let mut code = BitVec::<u8, Msb0>::new();
+    for symbol in symbols {
+        code.resize(lengths[symbol] as usize, false);
+        codes[symbol] = code.clone();
+
+        // Binary increment, dropping the carry once every code of this length is used
+        while code.pop() == Some(true) {}
+        code.push(true);
+        code.resize(lengths[symbol] as usize, false);
+    }
+
+    codes
+}
+
+/// Writes the 256 code lengths of a byte alphabet as a plain table of 8 bits each, smaller
+/// than the run-length encoding when nearly every byte is used and lengths seldom repeat
+pub fn serialize_code_length_table(lengths: &[u8; 256]) -> BitVec<u8, Msb0> {
+    lengths.view_bits::<Msb0>().to_bitvec()
+}
+
+/// Reads code lengths written by `serialize_code_length_table`
+pub fn deserialize_code_length_table(
+    bit_iter: &mut impl Iterator<Item = bool>,
+) -> Result<[u8; 256], DecompressError> {
+    let mut lengths = [0u8; 256];
+    for length in &mut lengths {
+        for _ in 0..8 {
+            *length = *length << 1 | bit_iter.next().ok_or(DecompressError::TruncatedTree)? as u8;
+        }
+    }
+    Ok(lengths)
+}
+
+/// Writes the 256 code lengths of a byte alphabet run-length encoded, as 2-bit tagged tokens:
+/// `00` and 8 bits for a run of 1-256 zeros, `01` and 8 bits for a single length, `10` and 4
+/// bits to repeat the previous length 1-16 times. Sparse alphabets and long runs of equal
+/// lengths come out far below the 2048 bits of `serialize_code_length_table`.
+pub fn serialize_code_lengths_rle(lengths: &[u8; 256]) -> BitVec<u8, Msb0> {
+    let mut bits = BitVec::<u8, Msb0>::new();
+    let mut push = |tag: u8, value: u8, width: usize| {
+        bits.extend_from_bitslice(&tag.view_bits::<Msb0>()[6..]);
+        bits.extend_from_bitslice(&value.view_bits::<Msb0>()[8 - width..]);
+    };
+
+    let mut i = 0;
+    while i < lengths.len() {
+        let length = lengths[i];
+        let run = lengths[i..].iter().take_while(|&&l| l == length).count();
+
+        if length == 0 {
+            push(RLE_ZEROS, (run - 1) as u8, 8);
+        } else {
+            push(RLE_LENGTH, length, 8);
+            let mut repeats = run - 1;
+            while repeats > 0 {
+                let count = repeats.min(16);
+                push(RLE_REPEAT, (count - 1) as u8, 4);
+                repeats -= count;
+            }
+        }
+        i += run;
+    }
+
+    bits
+}
+
+/// Reads code lengths written by `serialize_code_lengths_rle`
+pub fn deserialize_code_lengths_rle(
+    bit_iter: &mut impl Iterator<Item = bool>,
+) -> Result<[u8; 256], DecompressError> {
+    let mut read = |width: usize| -> Result<u8, DecompressError> {
+        let mut value = 0u8;
+        for _ in 0..width {
+            value = value << 1 | bit_iter.next().ok_or(DecompressError::TruncatedTree)? as u8;
+        }
+        Ok(value)
+    };
+
+    let mut lengths = [0u8; 256];
+    let mut i = 0;
+    while i < lengths.len() {
+        let (length, run) = match read(2)? {
+            RLE_ZEROS => (0, read(8)? as usize + 1),
+            RLE_LENGTH => (read(8)?, 1),
+            RLE_REPEAT if i > 0 && lengths[i - 1] != 0 => (lengths[i - 1], read(4)? as usize + 1),
+            _ => return Err(DecompressError::InvalidTree),
+        };
+
+        lengths
+            .get_mut(i..i + run)
+            .ok_or(DecompressError::InvalidTree)?
+            .fill(length);
+        i += run;
+    }
+
+    Ok(lengths)
+}
@@ -0,0 +1,46 @@
+use bitvec::prelude::*;
+
+use crate::{HuffmanArchive, HuffmanTree, Options};
+
+/// Reusable compressor that keeps its output buffer between calls, so compressing many
+/// similar inputs only grows it until it fits the largest archive
+#[derive(Debug, Clone, Default)]
+pub struct Compressor {
+    options: Options,
+    archive: BitVec<u8, Msb0>,
+}
+
+impl Compressor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_options(options: Options) -> Self {
+        Self::with_options_and_capacity(options, 0)
+    }
+
+    /// Pre-sizes the output buffer for archives of up to `capacity` bytes
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_options_and_capacity(Options::default(), capacity)
+    }
+
+    pub fn with_options_and_capacity(options: Options, capacity: usize) -> Self {
+        Self {
+            options,
+            archive: BitVec::with_capacity(capacity * 8),
+        }
+    }
+
+    /// Bytes the output buffer holds before it has to grow
+    pub fn capacity(&self) -> usize {
+        self.archive.capacity() / 8
+    }
+
+    /// Compresses `data` with a tree built for it, the result is valid until the next call
+    pub fn compress(&mut self, data: &[u8]) -> &[u8] {
+        self.archive.clear();
+        let tree = HuffmanTree::build(data);
+        HuffmanArchive::compress_into(data, &tree, &self.options, &mut self.archive);
+        self.archive.as_raw_slice()
+    }
+}
@@ -0,0 +1,170 @@
+use bitvec::prelude::*;
+
+use crate::{DecompressError, HuffmanNode, HuffmanTree};
+
+/// Hooks for counting decode work, `()` counts nothing and compiles away
+pub(crate) trait DecodeCounter {
+    fn transition(&mut self);
+    /// A symbol with a `length` bit code read in one lookup of the direct table
+    fn direct_lookup(&mut self, length: u8);
+    fn bits(&mut self, count: usize);
+}
+
+impl DecodeCounter for () {
+    fn transition(&mut self) {}
+    fn direct_lookup(&mut self, _length: u8) {}
+    fn bits(&mut self, _count: usize) {}
+}
+
+#[derive(Debug, Clone, Copy)]
+enum DecodeEntry {
+    Leaf(u8),
+    Internal([u32; 2]),
+}
+
+/// Flattened copy of a tree, so decoding indexes a `Vec` instead of chasing boxes
+#[derive(Debug, Clone)]
+pub struct DecodeTable {
+    entries: Vec<DecodeEntry>,
+    // When no code is longer than 8 bits: (byte, code length) for every value of the next 8
+    // input bits, so each symbol takes one lookup
+    direct: Option<Box<[(u8, u8); 256]>>,
+}
+
+impl DecodeTable {
+    pub fn new(tree: &HuffmanTree) -> Result<Self, DecompressError> {
+        // A lone leaf would decode every symbol from zero bits
+        if tree.root.byte.is_some() {
+            return Err(DecompressError::InvalidTree);
+        }
+
+        let mut table = Self {
+            entries: Vec::new(),
+            direct: None,
+        };
+        table.flatten(&tree.root)?;
+
+        if tree.max_leaf_depth() <= 8 {
+            let mut direct = Box::new([(0, 0); 256]);
+            table.fill_direct(0, 0, 0, &mut direct);
+            table.direct = Some(direct);
+        }
+
+        Ok(table)
+    }
+
+    fn flatten(&mut self, node: &HuffmanNode) -> Result<u32, DecompressError> {
+        let index = self.entries.len() as u32;
+
+        if let Some(byte) = node.byte {
+            self.entries.push(DecodeEntry::Leaf(byte));
+        } else {
+            self.entries.push(DecodeEntry::Internal([0, 0]));
+            let (Some(left), Some(right)) = (&node.l_child, &node.r_child) else {
+                return Err(DecompressError::InvalidTree);
+            };
+            let left = self.flatten(left)?;
+            let right = self.flatten(right)?;
+            self.entries[index as usize] = DecodeEntry::Internal([left, right]);
+        }
+
+        Ok(index)
+    }
+
+    // Every leaf, padding ones included, claims the entries whose top bits are its code
+    fn fill_direct(&self, index: usize, code: usize, depth: u8, direct: &mut [(u8, u8); 256]) {
+        match self.entries[index] {
+            DecodeEntry::Leaf(byte) => {
+                let first = code << (8 - depth);
+                direct[first..first + (1 << (8 - depth))].fill((byte, depth));
+            }
+            DecodeEntry::Internal([left, right]) => {
+                self.fill_direct(left as usize, code << 1, depth + 1, direct);
+                self.fill_direct(right as usize, code << 1 | 1, depth + 1, direct);
+            }
+        }
+    }
+
+    /// Decodes `output.len()` symbols from the bits of `bytes` starting at bit `start`,
+    /// returning the position of the first bit after them
+    pub(crate) fn decode(
+        &self,
+        bytes: &[u8],
+        start: usize,
+        output: &mut [u8],
+        counter: &mut impl DecodeCounter,
+    ) -> Result<usize, DecompressError> {
+        let bits = bytes.view_bits::<Msb0>();
+        let mut bit_iter = bits
+            .get(start..)
+            .ok_or(DecompressError::TruncatedPayload)?
+            .iter()
+            .by_vals();
+
+        // Root with two leaves, the shape `build` gives single-symbol input by pairing it with
+        // a dummy leaf for byte 0. Every bit is a whole symbol, so skip walking the table.
+        if let [_, DecodeEntry::Leaf(zero), DecodeEntry::Leaf(one)] = self.entries[..] {
+            for slot in output.iter_mut() {
+                let bit = bit_iter.next().ok_or(DecompressError::TruncatedPayload)?;
+                *slot = if bit { one } else { zero };
+                counter.transition();
+            }
+            return Ok(bits.len() - bit_iter.len());
+        }
+
+        if let Some(direct) = &self.direct {
+            return Self::decode_direct(direct, bytes, start, output, counter);
+        }
+
+        for slot in output.iter_mut() {
+            let mut current = 0;
+
+            loop {
+                let bit = bit_iter.next().ok_or(DecompressError::TruncatedPayload)?;
+
+                let DecodeEntry::Internal(children) = self.entries[current] else {
+                    unreachable!("decoding always restarts from an internal root");
+                };
+                current = children[bit as usize] as usize;
+                counter.transition();
+
+                if let DecodeEntry::Leaf(byte) = self.entries[current] {
+                    *slot = byte;
+                    break;
+                }
+            }
+        }
+
+        Ok(bits.len() - bit_iter.len())
+    }
+
+    fn decode_direct(
+        direct: &[(u8, u8); 256],
+        bytes: &[u8],
+        start: usize,
+        output: &mut [u8],
+        counter: &mut impl DecodeCounter,
+    ) -> Result<usize, DecompressError> {
+        let end = bytes.len() * 8;
+        let mut pos = start;
+
+        for slot in output.iter_mut() {
+            // The next 8 bits, zero padded past the end of the input
+            let index = pos / 8;
+            let window = u16::from_be_bytes([
+                bytes.get(index).copied().unwrap_or(0),
+                bytes.get(index + 1).copied().unwrap_or(0),
+            ]);
+            let (byte, length) = direct[(window << (pos % 8) >> 8) as usize];
+            counter.direct_lookup(length);
+
+            pos += length as usize;
+            if pos > end {
+                return Err(DecompressError::TruncatedPayload);
+            }
+            *slot = byte;
+        }
+
+        Ok(pos)
+    }
+}
@@ -0,0 +1,118 @@
+use crate::{ArchiveHeader, DecodeTable, DecompressError, HuffmanArchive, HuffmanTree};
+
+/// Reusable decompressor that keeps the decode table for a preset tree between calls, and for
+/// `decompress_buffered` its output buffer
+#[derive(Default)]
+pub struct Decompressor {
+    preset: Option<Preset>,
+    output: Vec<u8>,
+}
+
+struct Preset {
+    tree: HuffmanTree,
+    table: Option<DecodeTable>,
+    // Dictionary archives are coded with the canonical code of the tree's lengths
+    fingerprint: u64,
+    dictionary_table: Option<DecodeTable>,
+}
+
+impl Preset {
+    fn new(tree: HuffmanTree) -> Self {
+        Self {
+            fingerprint: tree.codebook_fingerprint(),
+            tree,
+            table: None,
+            dictionary_table: None,
+        }
+    }
+}
+
+impl Decompressor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_preset_tree(tree: HuffmanTree) -> Self {
+        Self {
+            preset: Some(Preset::new(tree)),
+            output: Vec::new(),
+        }
+    }
+
+    /// Pre-sizes the output buffer of `decompress_buffered` for up to `capacity` decoded bytes
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            preset: None,
+            output: Vec::with_capacity(capacity),
+        }
+    }
+
+    /// Bytes the output buffer holds before it has to grow
+    pub fn capacity(&self) -> usize {
+        self.output.capacity()
+    }
+
+    pub fn preset_tree(&self) -> Option<&HuffmanTree> {
+        self.preset.as_ref().map(|preset| &preset.tree)
+    }
+
+    /// Replaces the preset tree, dropping the decode tables cached for the old one
+    pub fn set_preset_tree(&mut self, tree: HuffmanTree) {
+        self.preset = Some(Preset::new(tree));
+    }
+
+    /// Decodes `archive_bytes`, with the preset tree if the archive leaves its tree out. For a
+    /// `DictMode::Reference` archive the preset tree must be the dictionary it names.
+    pub fn decompress(&mut self, archive_bytes: &[u8]) -> Result<Vec<u8>, DecompressError> {
+        let header = ArchiveHeader::parse(archive_bytes)?;
+        let preset = Self::preset_table(&mut self.preset, &header)?;
+        HuffmanArchive::decompress_with_table(archive_bytes, preset)
+    }
+
+    /// Like `decompress`, decoding into the buffer kept between calls, so decompressing many
+    /// similar archives only grows it until it fits the largest output. The result is valid
+    /// until the next call.
+    pub fn decompress_buffered(&mut self, archive_bytes: &[u8]) -> Result<&[u8], DecompressError> {
+        let header = ArchiveHeader::parse(archive_bytes)?;
+        let preset = Self::preset_table(&mut self.preset, &header)?;
+        HuffmanArchive::check_data_len(archive_bytes, &header)?;
+
+        self.output.clear();
+        self.output.resize(header.data_len as usize, 0);
+        HuffmanArchive::decompress_frame_into(
+            archive_bytes,
+            &header,
+            preset,
+            &mut (),
+            &mut self.output,
+        )?;
+        Ok(&self.output)
+    }
+
+    // The decode table for the preset tree, built on first use, if the archive needs one
+    fn preset_table<'a>(
+        preset: &'a mut Option<Preset>,
+        header: &ArchiveHeader,
+    ) -> Result<Option<&'a DecodeTable>, DecompressError> {
+        let Some(preset) = preset.as_mut().filter(|_| header.has_preset_tree()) else {
+            return Ok(None);
+        };
+        let table = match header.dictionary {
+            Some(expected) => {
+                HuffmanArchive::check_dictionary(expected, preset.fingerprint)?;
+                if preset.dictionary_table.is_none() {
+                    let code = HuffmanArchive::dictionary_code(&preset.tree)?;
+                    preset.dictionary_table = Some(DecodeTable::new(&code)?);
+                }
+                &preset.dictionary_table
+            }
+            None => {
+                if preset.table.is_none() {
+                    preset.table = Some(DecodeTable::new(&preset.tree)?);
+                }
+                &preset.table
+            }
+        };
+        Ok(table.as_ref())
+    }
+}
@@ -0,0 +1,106 @@
+use bitvec::prelude::*;
+
+use crate::{
+    ArchiveHeader, CompressError, DecodeTable, DecompressError, FLAG_CODE_LENGTHS, FLAG_DICTIONARY,
+    FLAG_PRESET_TREE, HuffmanArchive, HuffmanTree, Options, serialize_code_lengths_rle,
+};
+
+/// How `compress_with_dictionary` handles the dictionary
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DictMode {
+    /// Store the dictionary, so the archive decodes on its own and `extract_tree` gives the
+    /// dictionary to cache for later archives
+    Embed,
+    /// Leave the dictionary out, only its fingerprint is stored
+    Reference,
+}
+
+impl HuffmanArchive {
+    /// Codes `data` with the canonical code for `dictionary`'s code lengths and records their
+    /// `codebook_fingerprint` in the header, so a decoder can look the dictionary up in a cache
+    /// it filled from an earlier `DictMode::Embed` archive
+    pub fn compress_with_dictionary(
+        data: &[u8],
+        dictionary: &HuffmanTree,
+        mode: DictMode,
+        options: &Options,
+    ) -> Result<BitVec<u8, Msb0>, CompressError> {
+        let lengths = dictionary.code_lengths();
+        let tree = HuffmanTree::from_code_lengths(&lengths).unwrap();
+        let encoding_table = tree.get_encoding_table();
+
+        if let Some(offset) = data
+            .iter()
+            .position(|&byte| encoding_table[byte as usize].is_empty())
+        {
+            return Err(CompressError::SymbolNotInTree {
+                byte: data[offset],
+                offset,
+            });
+        }
+
+        let tree_bits = match mode {
+            DictMode::Embed => serialize_code_lengths_rle(&lengths),
+            DictMode::Reference => BitVec::new(),
+        };
+
+        let mut header = Self::new_header(tree_bits.len() as u64, data, options);
+        header.flags |= FLAG_DICTIONARY;
+        header.flags |= match mode {
+            DictMode::Embed => FLAG_CODE_LENGTHS,
+            DictMode::Reference => FLAG_PRESET_TREE,
+        };
+        header.dictionary = Some(dictionary.codebook_fingerprint());
+
+        let mut archive = BitVec::<u8, Msb0>::new();
+
+        header.write(&mut archive);
+
+        Self::write_tree(&header, &tree_bits, &mut archive);
+
+        Self::encode_payload(data, &encoding_table, &mut archive);
+
+        Self::seal_payload_checksum(&mut archive, 0);
+
+        Ok(archive)
+    }
+
+    /// Decompresses an archive from `compress_with_dictionary`. Any tree with the same code
+    /// lengths as the dictionary works, embedded dictionaries are read from the archive.
+    pub fn decompress_with_dictionary(
+        archive_bytes: &[u8],
+        dictionary: &HuffmanTree,
+    ) -> Result<Vec<u8>, DecompressError> {
+        let header = ArchiveHeader::parse(archive_bytes)?;
+        if !header.has_preset_tree() {
+            return Self::decompress(archive_bytes);
+        }
+
+        // A preset tree without a fingerprint is used as it is
+        let table = match header.dictionary {
+            Some(expected) => {
+                Self::check_dictionary(expected, dictionary.codebook_fingerprint())?;
+                DecodeTable::new(&Self::dictionary_code(dictionary)?)?
+            }
+            None => DecodeTable::new(dictionary)?,
+        };
+
+        Self::decompress_with_table(archive_bytes, Some(&table))
+    }
+
+    pub(crate) fn check_dictionary(expected: u64, actual: u64) -> Result<(), DecompressError> {
+        if expected != actual {
+            return Err(DecompressError::DictionaryMismatch { expected, actual });
+        }
+        Ok(())
+    }
+
+    // The canonical code with the dictionary's code lengths, which is what the archive was
+    // coded with whatever the dictionary's shape
+    pub(crate) fn dictionary_code(
+        dictionary: &HuffmanTree,
+    ) -> Result<HuffmanTree, DecompressError> {
+        HuffmanTree::from_code_lengths(&dictionary.code_lengths())
+            .ok_or(DecompressError::InvalidTree)
+    }
+}
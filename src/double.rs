@@ -0,0 +1,54 @@
+use bitvec::prelude::*;
+
+use crate::decode::DecodeCounter;
+use crate::{
+    DecodeTable, DecompressError, FLAG_DOUBLE_HUFFMAN, HuffmanArchive, HuffmanTree, Options,
+};
+
+impl HuffmanArchive {
+    /// Experimental: huffman codes `data`, then codes the byte-packed result of that first
+    /// pass (its tree shape and payload) again with a tree of its own. The first pass is
+    /// usually close to random, so this is most often a little larger than
+    /// `compress_with_options`. It pays off when the first pass's bytes repeat, e.g. for a short
+    /// pattern whose codes add up to a whole number of bytes: `"ab"` repeated codes to the
+    /// same byte over and over, which the second pass codes in one bit each.
+    pub fn compress_double(data: &[u8], options: &Options) -> BitVec<u8, Msb0> {
+        let tree = HuffmanTree::build(data);
+        let mut first_pass = tree.serialize();
+        Self::encode_payload(data, &tree.get_encoding_table(), &mut first_pass);
+        let first_pass = first_pass.into_vec();
+
+        let outer = HuffmanTree::build(&first_pass);
+        let (tree_bits, tree_encoding, encoding_table) = Self::smallest_tree_encoding(&outer);
+
+        let mut header = Self::new_header(tree_bits.len() as u64, data, options);
+        tree_encoding.mark(&mut header);
+        header.flags |= FLAG_DOUBLE_HUFFMAN;
+        header.first_pass_len = Some(first_pass.len() as u64);
+
+        let mut archive = BitVec::<u8, Msb0>::new();
+
+        header.write(&mut archive);
+
+        Self::write_tree(&header, &tree_bits, &mut archive);
+
+        Self::encode_payload(&first_pass, &encoding_table, &mut archive);
+
+        Self::seal_payload_checksum(&mut archive, 0);
+
+        archive
+    }
+
+    /// Decodes the output of the first pass of `compress_double` into `output`
+    pub(crate) fn decode_first_pass(
+        first_pass: &[u8],
+        output: &mut [u8],
+        counter: &mut impl DecodeCounter,
+    ) -> Result<(), DecompressError> {
+        let bits = first_pass.view_bits::<Msb0>();
+        let mut bit_iter = bits.iter().by_vals();
+        let table = DecodeTable::new(&HuffmanTree::deserialize_shape(&mut bit_iter)?)?;
+        table.decode(first_pass, bits.len() - bit_iter.len(), output, counter)?;
+        Ok(())
+    }
+}
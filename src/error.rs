@@ -0,0 +1,165 @@
+use std::fmt;
+use std::string::FromUtf8Error;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecompressError {
+    TooShort,
+    InvalidMagic,
+    UnsupportedVersion(u8),
+    UnsupportedFlags(u16),
+    UnsupportedExtendedFlags(u16),
+    TruncatedTree,
+    InvalidTree,
+    MissingPresetTree,
+    TruncatedPayload,
+    ChecksumMismatch {
+        expected: u32,
+        actual: u32,
+    },
+    InvalidUtf8(FromUtf8Error),
+    NotRecords,
+    RecordOutOfRange {
+        index: usize,
+        count: usize,
+    },
+    InvalidBlockIndex,
+    InvalidLeafWidth(u8),
+    NoEmbeddedTree,
+    RangeOutOfBounds {
+        start: usize,
+        end: usize,
+        len: usize,
+    },
+    UnsupportedPrePass(u8),
+    BufferTooSmall {
+        needed: usize,
+        available: usize,
+    },
+    MissingDictionary(u64),
+    DictionaryMismatch {
+        expected: u64,
+        actual: u64,
+    },
+    InvalidLineEndings,
+    NotFiles,
+    NotMultistream,
+    UnsafePath(String),
+    PayloadChecksumMismatch {
+        expected: u32,
+        actual: u32,
+    },
+}
+
+impl fmt::Display for DecompressError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooShort => write!(f, "file too small"),
+            Self::InvalidMagic => write!(f, "invalid magic number"),
+            Self::UnsupportedVersion(version) => {
+                write!(f, "unsupported format version {version}")
+            }
+            Self::UnsupportedFlags(flags) => write!(f, "unsupported header flags {flags:#06x}"),
+            Self::UnsupportedExtendedFlags(flags) => {
+                write!(f, "unsupported extended header flags {flags:#06x}")
+            }
+            Self::TruncatedTree => write!(f, "bitstream ended inside the tree"),
+            Self::InvalidTree => write!(f, "tree cannot be used for decoding"),
+            Self::MissingPresetTree => {
+                write!(
+                    f,
+                    "archive was compressed with a preset tree that was not provided"
+                )
+            }
+            Self::TruncatedPayload => write!(f, "ran out of bits before reaching data_len"),
+            Self::ChecksumMismatch { expected, actual } => write!(
+                f,
+                "checksum mismatch (expected {expected:#010x}, got {actual:#010x})"
+            ),
+            Self::InvalidUtf8(err) => write!(f, "decompressed data is not UTF-8: {err}"),
+            Self::NotRecords => write!(f, "archive has no record index"),
+            Self::RecordOutOfRange { index, count } => {
+                write!(f, "record {index} requested but the archive has {count}")
+            }
+            Self::InvalidBlockIndex => write!(f, "block index does not match the blocks"),
+            Self::InvalidLeafWidth(width) => write!(f, "leaf width {width} is not 1 to 8 bits"),
+            Self::NoEmbeddedTree => write!(f, "archive does not contain a single tree"),
+            Self::RangeOutOfBounds { start, end, len } => {
+                write!(f, "range {start}..{end} is outside the {len} decoded bytes")
+            }
+            Self::UnsupportedPrePass(bits) => write!(f, "unsupported pre-passes {bits:#04x}"),
+            Self::BufferTooSmall { needed, available } => write!(
+                f,
+                "decoded data needs {needed} bytes but the buffer holds {available}"
+            ),
+            Self::MissingDictionary(fingerprint) => {
+                write!(
+                    f,
+                    "archive needs dictionary {fingerprint:016x}, none was provided"
+                )
+            }
+            Self::DictionaryMismatch { expected, actual } => write!(
+                f,
+                "archive needs dictionary {expected:016x} but was given {actual:016x}"
+            ),
+            Self::InvalidLineEndings => {
+                write!(f, "CRLF count does not match the decoded line endings")
+            }
+            Self::NotFiles => write!(f, "archive does not hold files"),
+            Self::NotMultistream => write!(f, "archive does not hold separate streams"),
+            Self::UnsafePath(path) => {
+                write!(f, "refusing to extract {path} outside the target directory")
+            }
+            Self::PayloadChecksumMismatch { expected, actual } => write!(
+                f,
+                "compressed data checksum mismatch (expected {expected:#010x}, got {actual:#010x})"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DecompressError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::InvalidUtf8(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompressError {
+    SymbolNotInTree { byte: u8, offset: usize },
+    UnsupportedFormatVersion(u8),
+    LeafWidthTooNarrow { width: u8, needed: u8 },
+    Cancelled,
+    BufferTooSmall { needed: usize, available: usize },
+}
+
+impl fmt::Display for CompressError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::SymbolNotInTree { byte, offset } => {
+                write!(
+                    f,
+                    "byte {byte:#04x} at offset {offset} has no code in the tree"
+                )
+            }
+            Self::UnsupportedFormatVersion(version) => {
+                write!(f, "format version {version} cannot be written")
+            }
+            Self::LeafWidthTooNarrow { width, needed } => {
+                write!(
+                    f,
+                    "leaf width {width} is too narrow, the tree needs {needed}"
+                )
+            }
+            Self::Cancelled => write!(f, "compression was cancelled"),
+            Self::BufferTooSmall { needed, available } => write!(
+                f,
+                "archive needs {needed} bytes but the buffer holds {available}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CompressError {}
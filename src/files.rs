@@ -0,0 +1,97 @@
+use bitvec::prelude::*;
+use std::fs;
+use std::io;
+use std::path::{Component, Path};
+
+use crate::{ArchiveHeader, DecompressError, FLAG_FILES, FLAG_RECORDS, HuffmanArchive, Options};
+
+impl HuffmanArchive {
+    /// Packs files into one archive, as records of each file's relative path (`/` separated)
+    /// followed by its contents, which all share one tree
+    pub fn compress_files(files: &[(&str, &[u8])], options: &Options) -> BitVec<u8, Msb0> {
+        let records: Vec<&[u8]> = files
+            .iter()
+            .flat_map(|&(path, contents)| [path.as_bytes(), contents])
+            .collect();
+        Self::compress_records_with_flags(&records, FLAG_RECORDS | FLAG_FILES, options)
+    }
+
+    /// The path and contents of every file in an archive from `compress_files`
+    pub fn decompress_files(
+        archive_bytes: &[u8],
+    ) -> Result<Vec<(String, Vec<u8>)>, DecompressError> {
+        let header = ArchiveHeader::parse(archive_bytes)?;
+        let count = header.entry_count.unwrap_or(0) as usize;
+        if !header.has_files() || !header.has_records() || !count.is_multiple_of(2) {
+            return Err(DecompressError::NotFiles);
+        }
+
+        let data = Self::decompress(archive_bytes)?;
+        let mut rest = &data[..];
+        let mut files = Vec::with_capacity(count / 2);
+        for file in 0..count / 2 {
+            let (_, path_len) = header.index_entry(archive_bytes, 2 * file)?;
+            let (_, contents_len) = header.index_entry(archive_bytes, 2 * file + 1)?;
+            if path_len
+                .checked_add(contents_len)
+                .is_none_or(|len| len > rest.len())
+            {
+                return Err(DecompressError::InvalidBlockIndex);
+            }
+
+            let (path, tail) = rest.split_at(path_len);
+            let (contents, tail) = tail.split_at(contents_len);
+            let path = String::from_utf8(path.to_vec()).map_err(DecompressError::InvalidUtf8)?;
+            files.push((path, contents.to_vec()));
+            rest = tail;
+        }
+
+        Ok(files)
+    }
+
+    /// Writes the files of an archive from `compress_files` under `target`, creating
+    /// directories as needed. Before anything is written, every path is checked to be
+    /// relative, free of `..` and not passing through a symlink already in `target`, failing
+    /// with `DecompressError::UnsafePath` otherwise. Extraction writes no symlinks itself, so
+    /// the check holds for the directories it creates too.
+    pub fn extract_files(archive_bytes: &[u8], target: &Path) -> io::Result<()> {
+        let files = Self::decompress_files(archive_bytes).map_err(invalid_data)?;
+
+        if let Some((path, _)) = files
+            .iter()
+            .find(|(path, _)| !is_safe_path(path) || through_symlink(target, Path::new(path)))
+        {
+            return Err(invalid_data(DecompressError::UnsafePath(path.clone())));
+        }
+
+        for (path, contents) in files {
+            let destination = target.join(&path);
+            fs::create_dir_all(destination.parent().unwrap())?;
+            fs::write(destination, contents)?;
+        }
+
+        Ok(())
+    }
+}
+
+// Relative, naming a file and never stepping up with `..`
+fn is_safe_path(path: &str) -> bool {
+    let path = Path::new(path);
+    path.file_name().is_some()
+        && path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+}
+
+// A symlink already under `target` on the way to `path` could point anywhere
+fn through_symlink(target: &Path, path: &Path) -> bool {
+    let mut current = target.to_path_buf();
+    path.components().any(|component| {
+        current.push(component);
+        fs::symlink_metadata(&current).is_ok_and(|meta| meta.is_symlink())
+    })
+}
+
+fn invalid_data(err: DecompressError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
@@ -0,0 +1,180 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::{HuffmanArchive, HuffmanTree, Options};
+
+/// Splits everything written to it into self-contained archives ("frames") of at most
+/// `frame_size` input bytes each. Frames are byte aligned, so the output is simply the
+/// frames back to back and `HuffmanArchive::decompress_frames` decodes it in order. Like
+/// `PuhlEncoder`, the last frame is written on `finish` or, ignoring errors, when the encoder
+/// is dropped.
+pub struct FrameEncoder<W: Write> {
+    inner: Option<W>,
+    frame_size: usize,
+    options: Options,
+    buffer: Vec<u8>,
+    max_interval: Option<Duration>,
+    /// When the first byte of the buffered frame was written
+    frame_started: Option<Instant>,
+}
+
+impl<W: Write> FrameEncoder<W> {
+    pub fn new(inner: W, frame_size: usize) -> Self {
+        Self::with_options(inner, frame_size, Options::default())
+    }
+
+    pub fn with_options(inner: W, frame_size: usize, options: Options) -> Self {
+        assert!(frame_size > 0, "frame size must be at least one byte");
+        Self {
+            inner: Some(inner),
+            frame_size,
+            options,
+            buffer: Vec::with_capacity(frame_size),
+            max_interval: None,
+            frame_started: None,
+        }
+    }
+
+    /// Like `new`, also ending a frame once its first byte has waited `max_interval`, to bound
+    /// the latency of a slow trickle of input. The interval is checked on each write and by
+    /// `flush_if_due`, which an otherwise idle writer can call from a timer.
+    pub fn with_flush_policy(inner: W, max_bytes: usize, max_interval: Duration) -> Self {
+        let mut encoder = Self::new(inner, max_bytes);
+        encoder.max_interval = Some(max_interval);
+        encoder
+    }
+
+    /// Writes the buffered frame if it has been waiting for at least the flush interval
+    pub fn flush_if_due(&mut self) -> io::Result<()> {
+        if self.is_due() {
+            self.flush_frame()?;
+        }
+        Ok(())
+    }
+
+    fn is_due(&self) -> bool {
+        match (self.max_interval, self.frame_started) {
+            (Some(interval), Some(started)) => started.elapsed() >= interval,
+            _ => false,
+        }
+    }
+
+    /// Writes whatever is buffered as a frame, even if it is shorter than `frame_size`
+    pub fn flush_frame(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+
+        let frame = encode_frame(&self.buffer, &self.options);
+        self.inner.as_mut().unwrap().write_all(&frame)?;
+        self.buffer.clear();
+        self.frame_started = None;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        self.flush()?;
+        Ok(self.inner.take().unwrap())
+    }
+}
+
+impl<W: Write> Write for FrameEncoder<W> {
+    // A full frame is written out before taking more input, so that a failing inner writer
+    // returns the error without having accepted any of `buf`
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.buffer.len() == self.frame_size || self.is_due() {
+            self.flush_frame()?;
+        }
+
+        let room = self.frame_size - self.buffer.len();
+        let taken = room.min(buf.len());
+        self.buffer.extend_from_slice(&buf[..taken]);
+        if taken > 0 && self.frame_started.is_none() {
+            self.frame_started = Some(Instant::now());
+        }
+
+        Ok(taken)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.flush_frame()?;
+        self.inner.as_mut().unwrap().flush()
+    }
+}
+
+impl<W: Write> Drop for FrameEncoder<W> {
+    fn drop(&mut self) {
+        if self.inner.is_some() {
+            let _ = self.flush();
+        }
+    }
+}
+
+/// Compresses `data` into a single byte-aligned frame
+pub fn encode_frame(data: &[u8], options: &Options) -> Vec<u8> {
+    let tree = HuffmanTree::build(data);
+    HuffmanArchive::compress_with_options(data, &tree, options).into_vec()
+}
+
+/// A file that only ever grows by whole frames, so a long-running process can keep appending
+/// compressed data without rewriting what is already there. Every frame is encoded in full
+/// before being handed to the file in one write, and the whole file reads back with
+/// `HuffmanArchive::decompress_frames`. A crash during a write can still leave a torn frame at
+/// the end, `read` skips it and `recover` cuts it off.
+pub struct ArchiveLog {
+    file: File,
+    options: Options,
+}
+
+impl ArchiveLog {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::open_with_options(path, Options::default())
+    }
+
+    pub fn open_with_options(path: impl AsRef<Path>, options: Options) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self { file, options })
+    }
+
+    /// Appends `data` as one frame and waits for it to reach the disk
+    pub fn append(&mut self, data: &[u8]) -> io::Result<()> {
+        if data.is_empty() {
+            return Ok(());
+        }
+
+        self.file.write_all(&encode_frame(data, &self.options))?;
+        self.file.sync_data()
+    }
+
+    /// Decodes the log up to its last complete frame whose checksum matches, leaving out a
+    /// torn frame and anything after it
+    pub fn read(path: impl AsRef<Path>) -> io::Result<Vec<u8>> {
+        let log = fs::read(path)?;
+        let mut output = Vec::new();
+        let mut rest = &log[..];
+        while let Ok((frame, consumed)) = HuffmanArchive::decompress_first_frame(rest) {
+            output.extend_from_slice(&frame);
+            rest = &rest[consumed..];
+        }
+        Ok(output)
+    }
+
+    /// Truncates the log after its last complete frame whose checksum matches, so appends from
+    /// here on read back. Returns how many bytes were cut off.
+    pub fn recover(path: impl AsRef<Path>) -> io::Result<u64> {
+        let log = fs::read(&path)?;
+        let mut valid = 0;
+        while let Ok((_, consumed)) = HuffmanArchive::decompress_first_frame(&log[valid..]) {
+            valid += consumed;
+        }
+
+        if valid < log.len() {
+            let file = OpenOptions::new().write(true).open(path)?;
+            file.set_len(valid as u64)?;
+            file.sync_all()?;
+        }
+        Ok((log.len() - valid) as u64)
+    }
+}
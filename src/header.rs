@@ -0,0 +1,625 @@
+use bitvec::prelude::*;
+use std::fmt;
+use std::ops::Range;
+
+use crate::prepass::pre_passes_in;
+use crate::{DecompressError, PrePass};
+
+pub const MAGIC: &[u8; 2] = b"JP";
+/// Version written by this crate. For a given version, the same input and options always
+/// produce the same bytes, whichever release of the crate wrote them.
+///
+/// Version 2 may store the tree as code lengths and has the extended flags. Version 1 always
+/// stores the tree shape.
+pub const FORMAT_VERSION: u8 = 2;
+
+/// Versions this crate reads and `compress_with_format_version` can still write
+pub const SUPPORTED_FORMAT_VERSIONS: &[u8] = &[1, 2];
+
+/// A CRC-32 of the original data follows `data_len`
+pub const FLAG_CHECKSUM: u16 = 1 << 0;
+/// The tree is left out and must be supplied by the decoder
+pub const FLAG_PRESET_TREE: u16 = 1 << 1;
+/// The payload is the original bytes, there is no tree
+pub const FLAG_STORED: u16 = 1 << 2;
+/// A record count and an index of records follow the fixed fields
+pub const FLAG_RECORDS: u16 = 1 << 3;
+/// The body is a run of independent frames, listed in an index like records are
+pub const FLAG_BLOCKS: u16 = 1 << 4;
+/// A leaf width byte follows, leaves in the tree use that many bits instead of 8
+pub const FLAG_LEAF_WIDTH: u16 = 1 << 5;
+/// A byte follows with the set of `PrePass`es run over the input before coding
+pub const FLAG_PRE_PASSES: u16 = 1 << 6;
+/// The tree is stored as run-length encoded code lengths of a canonical code
+pub const FLAG_CODE_LENGTHS: u16 = 1 << 7;
+/// A user comment follows, as a 16-bit length and that many bytes of UTF-8
+pub const FLAG_COMMENT: u16 = 1 << 8;
+/// The tree is a reusable dictionary and its `codebook_fingerprint` follows as a u64. With
+/// `FLAG_PRESET_TREE` the decoder has to supply the dictionary, otherwise it is embedded.
+pub const FLAG_DICTIONARY: u16 = 1 << 9;
+/// Every line ended in CRLF and is coded ending in LF alone. A u64 count of the dropped CRs
+/// follows, `data_len` and the checksum still cover them.
+pub const FLAG_CRLF: u16 = 1 << 10;
+/// Set with `FLAG_RECORDS`, the records alternate between a file's relative path and its
+/// contents
+pub const FLAG_FILES: u16 = 1 << 11;
+/// The length in bytes of everything after the header follows as a u64, then a CRC-32 of
+/// those bytes, so corruption in transit shows up without decoding
+pub const FLAG_PAYLOAD_CHECKSUM: u16 = 1 << 12;
+/// The payload decodes to the byte-packed output of a first huffman pass, its tree shape then
+/// its payload, which in turn decodes to the data. The first pass's length in bytes follows
+/// as a u64.
+pub const FLAG_DOUBLE_HUFFMAN: u16 = 1 << 13;
+/// Set with `FLAG_BLOCKS`, every block is a separate stream, empty ones included
+pub const FLAG_STREAMS: u16 = 1 << 14;
+/// A u16 of `EXTENDED_FLAG_*` bits follows the other optional fields. Version 2 and later,
+/// in version 1 the bit is unknown.
+pub const FLAG_EXTENDED: u16 = 1 << 15;
+
+/// The tree is stored as a plain table of 256 8-bit code lengths of a canonical code
+pub const EXTENDED_FLAG_LENGTH_TABLE: u16 = 1 << 0;
+
+// Where an indexed record or block starts (payload bits for records, body bytes for blocks)
+// and how many bytes it decodes to
+pub(crate) const INDEX_ENTRY_LEN: usize = 8 + 8;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveHeader {
+    pub version: u8,
+    pub flags: u16,
+    pub tree_len: u64,
+    pub data_len: u64,
+    pub checksum: Option<u32>,
+    pub entry_count: Option<u64>,
+    pub leaf_width: Option<u8>,
+    pub pre_passes: Option<u8>,
+    pub comment: Option<String>,
+    pub dictionary: Option<u64>,
+    pub crlf_count: Option<u64>,
+    pub payload_len: Option<u64>,
+    pub payload_checksum: Option<u32>,
+    pub first_pass_len: Option<u64>,
+    pub extended_flags: Option<u16>,
+}
+
+impl ArchiveHeader {
+    // magic + version + flags + tree_len + data_len
+    pub const MIN_LEN: usize = 2 + 1 + 2 + 8 + 8;
+    pub const MAX_COMMENT_LEN: usize = u16::MAX as usize;
+
+    // Every flag bit version 1 knows how to decode, later versions add `FLAG_EXTENDED`
+    pub const KNOWN_FLAGS: u16 = FLAG_CHECKSUM
+        | FLAG_PRESET_TREE
+        | FLAG_STORED
+        | FLAG_RECORDS
+        | FLAG_BLOCKS
+        | FLAG_LEAF_WIDTH
+        | FLAG_PRE_PASSES
+        | FLAG_CODE_LENGTHS
+        | FLAG_COMMENT
+        | FLAG_DICTIONARY
+        | FLAG_CRLF
+        | FLAG_FILES
+        | FLAG_PAYLOAD_CHECKSUM
+        | FLAG_DOUBLE_HUFFMAN
+        | FLAG_STREAMS;
+
+    // Every extended flag bit this version knows how to decode
+    pub const KNOWN_EXTENDED_FLAGS: u16 = EXTENDED_FLAG_LENGTH_TABLE;
+
+    pub fn new(tree_len: u64, data_len: u64, checksum: Option<u32>) -> Self {
+        Self {
+            version: FORMAT_VERSION,
+            flags: if checksum.is_some() { FLAG_CHECKSUM } else { 0 },
+            tree_len,
+            data_len,
+            checksum,
+            entry_count: None,
+            leaf_width: None,
+            pre_passes: None,
+            comment: None,
+            dictionary: None,
+            crlf_count: None,
+            payload_len: None,
+            payload_checksum: None,
+            first_pass_len: None,
+            extended_flags: None,
+        }
+    }
+
+    pub fn has_checksum(&self) -> bool {
+        self.flags & FLAG_CHECKSUM != 0
+    }
+
+    pub fn has_preset_tree(&self) -> bool {
+        self.flags & FLAG_PRESET_TREE != 0
+    }
+
+    pub fn is_stored(&self) -> bool {
+        self.flags & FLAG_STORED != 0
+    }
+
+    pub fn has_records(&self) -> bool {
+        self.flags & FLAG_RECORDS != 0
+    }
+
+    pub fn has_blocks(&self) -> bool {
+        self.flags & FLAG_BLOCKS != 0
+    }
+
+    pub fn has_leaf_width(&self) -> bool {
+        self.flags & FLAG_LEAF_WIDTH != 0
+    }
+
+    pub fn has_pre_passes(&self) -> bool {
+        self.flags & FLAG_PRE_PASSES != 0
+    }
+
+    pub fn has_code_lengths(&self) -> bool {
+        self.flags & FLAG_CODE_LENGTHS != 0
+    }
+
+    pub fn has_length_table(&self) -> bool {
+        self.extended_flags.unwrap_or(0) & EXTENDED_FLAG_LENGTH_TABLE != 0
+    }
+
+    pub fn has_comment(&self) -> bool {
+        self.flags & FLAG_COMMENT != 0
+    }
+
+    pub fn has_dictionary(&self) -> bool {
+        self.flags & FLAG_DICTIONARY != 0
+    }
+
+    pub fn has_crlf(&self) -> bool {
+        self.flags & FLAG_CRLF != 0
+    }
+
+    pub fn has_files(&self) -> bool {
+        self.flags & FLAG_FILES != 0
+    }
+
+    pub fn has_payload_checksum(&self) -> bool {
+        self.flags & FLAG_PAYLOAD_CHECKSUM != 0
+    }
+
+    pub fn has_double_huffman(&self) -> bool {
+        self.flags & FLAG_DOUBLE_HUFFMAN != 0
+    }
+
+    pub fn has_streams(&self) -> bool {
+        self.flags & FLAG_STREAMS != 0
+    }
+
+    pub fn has_extended_flags(&self) -> bool {
+        self.flags & FLAG_EXTENDED != 0
+    }
+
+    /// Sets `bits` in the extended flags, adding the field if it is not there yet
+    pub fn set_extended_flags(&mut self, bits: u16) {
+        self.flags |= FLAG_EXTENDED;
+        *self.extended_flags.get_or_insert(0) |= bits;
+    }
+
+    /// Bits per leaf in the serialized tree
+    pub fn leaf_bits(&self) -> u8 {
+        self.leaf_width.unwrap_or(8)
+    }
+
+    /// Records and blocks both keep an index between the header and the body
+    pub fn has_index(&self) -> bool {
+        self.has_records() || self.has_blocks()
+    }
+
+    /// Size of the header in bytes, including the optional fields
+    pub fn byte_len(&self) -> usize {
+        let mut len = Self::MIN_LEN;
+        if self.has_checksum() {
+            len += 4;
+        }
+        if self.has_index() {
+            len += 8;
+        }
+        if self.has_leaf_width() {
+            len += 1;
+        }
+        if self.has_pre_passes() {
+            len += 1;
+        }
+        if self.has_comment() {
+            len += 2 + self.comment.as_ref().map_or(0, String::len);
+        }
+        if self.has_dictionary() {
+            len += 8;
+        }
+        if self.has_crlf() {
+            len += 8;
+        }
+        if self.has_payload_checksum() {
+            len += 8 + 4;
+        }
+        if self.has_double_huffman() {
+            len += 8;
+        }
+        if self.has_extended_flags() {
+            len += 2;
+        }
+        len
+    }
+
+    /// Offset of the tree, or of the data in a stored archive, past the header and any index
+    pub fn body_offset(&self) -> usize {
+        // Saturates for a corrupt entry count, so reads from there fail rather than wrap
+        (self.entry_count.unwrap_or(0) as usize)
+            .saturating_mul(INDEX_ENTRY_LEN)
+            .saturating_add(self.byte_len())
+    }
+
+    /// Reads entry `index` of the record or block index as (offset, decoded length)
+    pub(crate) fn index_entry(
+        &self,
+        bytes: &[u8],
+        index: usize,
+    ) -> Result<(usize, usize), DecompressError> {
+        let start = self.byte_len() + index * INDEX_ENTRY_LEN;
+        let entry = bytes
+            .get(start..start + INDEX_ENTRY_LEN)
+            .ok_or(DecompressError::TooShort)?;
+        Ok((
+            u64::from_be_bytes(entry[0..8].try_into().unwrap()) as usize,
+            u64::from_be_bytes(entry[8..16].try_into().unwrap()) as usize,
+        ))
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<Self, DecompressError> {
+        if bytes.len() < 2 {
+            return Err(DecompressError::TooShort);
+        }
+
+        if &bytes[0..2] != MAGIC {
+            return Err(DecompressError::InvalidMagic);
+        }
+
+        if bytes.len() < Self::MIN_LEN {
+            return Err(DecompressError::TooShort);
+        }
+
+        let version = bytes[2];
+        if !SUPPORTED_FORMAT_VERSIONS.contains(&version) {
+            return Err(DecompressError::UnsupportedVersion(version));
+        }
+
+        let flags = u16::from_be_bytes(bytes[3..5].try_into().unwrap());
+        let known_flags = if version >= 2 {
+            Self::KNOWN_FLAGS | FLAG_EXTENDED
+        } else {
+            Self::KNOWN_FLAGS
+        };
+        if flags & !known_flags != 0 {
+            return Err(DecompressError::UnsupportedFlags(flags & !known_flags));
+        }
+
+        let mut header = Self {
+            version,
+            flags,
+            tree_len: u64::from_be_bytes(bytes[5..13].try_into().unwrap()),
+            data_len: u64::from_be_bytes(bytes[13..21].try_into().unwrap()),
+            checksum: None,
+            entry_count: None,
+            leaf_width: None,
+            pre_passes: None,
+            comment: None,
+            dictionary: None,
+            crlf_count: None,
+            payload_len: None,
+            payload_checksum: None,
+            first_pass_len: None,
+            extended_flags: None,
+        };
+
+        if bytes.len() < header.byte_len() {
+            return Err(DecompressError::TooShort);
+        }
+
+        // Optional fields follow in flag order
+        let mut pos = Self::MIN_LEN;
+        if header.has_checksum() {
+            header.checksum = Some(u32::from_be_bytes(bytes[pos..pos + 4].try_into().unwrap()));
+            pos += 4;
+        }
+        if header.has_index() {
+            header.entry_count = Some(u64::from_be_bytes(bytes[pos..pos + 8].try_into().unwrap()));
+            pos += 8;
+        }
+        if header.has_leaf_width() {
+            let width = bytes[pos];
+            if !(1..=8).contains(&width) {
+                return Err(DecompressError::InvalidLeafWidth(width));
+            }
+            header.leaf_width = Some(width);
+            pos += 1;
+        }
+        if header.has_pre_passes() {
+            let pre_passes = bytes[pos];
+            if pre_passes & !PrePass::KNOWN != 0 {
+                return Err(DecompressError::UnsupportedPrePass(
+                    pre_passes & !PrePass::KNOWN,
+                ));
+            }
+            header.pre_passes = Some(pre_passes);
+            pos += 1;
+        }
+        if header.has_comment() {
+            let len = bytes
+                .get(pos..pos + 2)
+                .map(|len| u16::from_be_bytes(len.try_into().unwrap()) as usize)
+                .ok_or(DecompressError::TooShort)?;
+            let comment = bytes
+                .get(pos + 2..pos + 2 + len)
+                .ok_or(DecompressError::TooShort)?;
+            header.comment =
+                Some(String::from_utf8(comment.to_vec()).map_err(DecompressError::InvalidUtf8)?);
+            pos += 2 + len;
+        }
+        if header.has_dictionary() {
+            // The comment's length was not known when the size was checked above
+            let fingerprint = bytes.get(pos..pos + 8).ok_or(DecompressError::TooShort)?;
+            header.dictionary = Some(u64::from_be_bytes(fingerprint.try_into().unwrap()));
+            pos += 8;
+        }
+        if header.has_crlf() {
+            let count = bytes.get(pos..pos + 8).ok_or(DecompressError::TooShort)?;
+            header.crlf_count = Some(u64::from_be_bytes(count.try_into().unwrap()));
+            pos += 8;
+        }
+        if header.has_payload_checksum() {
+            let fields = bytes.get(pos..pos + 12).ok_or(DecompressError::TooShort)?;
+            header.payload_len = Some(u64::from_be_bytes(fields[..8].try_into().unwrap()));
+            header.payload_checksum = Some(u32::from_be_bytes(fields[8..].try_into().unwrap()));
+            pos += 12;
+        }
+        if header.has_double_huffman() {
+            let len = bytes.get(pos..pos + 8).ok_or(DecompressError::TooShort)?;
+            header.first_pass_len = Some(u64::from_be_bytes(len.try_into().unwrap()));
+            pos += 8;
+        }
+        if header.has_extended_flags() {
+            let extended = bytes.get(pos..pos + 2).ok_or(DecompressError::TooShort)?;
+            let extended = u16::from_be_bytes(extended.try_into().unwrap());
+            if extended & !Self::KNOWN_EXTENDED_FLAGS != 0 {
+                return Err(DecompressError::UnsupportedExtendedFlags(
+                    extended & !Self::KNOWN_EXTENDED_FLAGS,
+                ));
+            }
+            header.extended_flags = Some(extended);
+        }
+
+        Ok(header)
+    }
+
+    pub fn write(&self, archive: &mut BitVec<u8, Msb0>) {
+        self.encode(|field| archive.extend_from_bitslice(field.view_bits::<Msb0>()));
+    }
+
+    /// Hands the encoded header to `put` one field at a time, optional fields are written
+    /// whenever they are set
+    pub(crate) fn encode(&self, mut put: impl FnMut(&[u8])) {
+        self.encode_fields(|_, bytes| put(bytes));
+    }
+
+    /// Byte range of every field in the encoded header, in order, back to back from 0 to
+    /// `byte_len`
+    pub fn field_ranges(&self) -> Vec<(HeaderField, Range<usize>)> {
+        let mut ranges = Vec::new();
+        let mut pos = 0;
+        self.encode_fields(|field, bytes| {
+            ranges.push((field, pos..pos + bytes.len()));
+            pos += bytes.len();
+        });
+        ranges
+    }
+
+    fn encode_fields(&self, mut put: impl FnMut(HeaderField, &[u8])) {
+        put(HeaderField::Magic, MAGIC);
+        put(HeaderField::Version, &[self.version]);
+        put(HeaderField::Flags, &self.flags.to_be_bytes());
+        put(HeaderField::TreeLen, &self.tree_len.to_be_bytes());
+        put(HeaderField::DataLen, &self.data_len.to_be_bytes());
+        if let Some(checksum) = self.checksum {
+            put(HeaderField::Checksum, &checksum.to_be_bytes());
+        }
+        if let Some(entry_count) = self.entry_count {
+            put(HeaderField::EntryCount, &entry_count.to_be_bytes());
+        }
+        if let Some(leaf_width) = self.leaf_width {
+            put(HeaderField::LeafWidth, &[leaf_width]);
+        }
+        if let Some(pre_passes) = self.pre_passes {
+            put(HeaderField::PrePasses, &[pre_passes]);
+        }
+        if let Some(comment) = &self.comment {
+            put(
+                HeaderField::CommentLen,
+                &(comment.len() as u16).to_be_bytes(),
+            );
+            put(HeaderField::Comment, comment.as_bytes());
+        }
+        if let Some(dictionary) = self.dictionary {
+            put(HeaderField::Dictionary, &dictionary.to_be_bytes());
+        }
+        if let Some(crlf_count) = self.crlf_count {
+            put(HeaderField::CrlfCount, &crlf_count.to_be_bytes());
+        }
+        if let (Some(len), Some(checksum)) = (self.payload_len, self.payload_checksum) {
+            put(HeaderField::PayloadLen, &len.to_be_bytes());
+            put(HeaderField::PayloadChecksum, &checksum.to_be_bytes());
+        }
+        if let Some(first_pass_len) = self.first_pass_len {
+            put(HeaderField::FirstPassLen, &first_pass_len.to_be_bytes());
+        }
+        if let Some(extended_flags) = self.extended_flags {
+            put(HeaderField::ExtendedFlags, &extended_flags.to_be_bytes());
+        }
+    }
+
+    pub fn describe(&self) -> ArchiveDescription {
+        ArchiveDescription {
+            version: self.version,
+            block_type: if self.is_stored() {
+                BlockType::Stored
+            } else if self.has_files() {
+                BlockType::Files
+            } else if self.has_records() {
+                BlockType::Records
+            } else if self.has_streams() {
+                BlockType::Streams
+            } else if self.has_blocks() {
+                BlockType::Blocks
+            } else {
+                BlockType::Single
+            },
+            checksum: if self.has_checksum() {
+                ChecksumKind::Crc32
+            } else {
+                ChecksumKind::None
+            },
+            endianness: Endianness::Big,
+            leaf_width: self.leaf_bits(),
+            preset_tree: self.has_preset_tree(),
+            pre_passes_applied: self.pre_passes.is_some_and(|mask| mask != 0),
+            pre_passes: pre_passes_in(self.pre_passes.unwrap_or(0)).collect(),
+            comment: self.comment.clone(),
+            dictionary: self.dictionary,
+            crlf_line_endings: self.has_crlf(),
+            payload_checksum: self.has_payload_checksum(),
+            double_huffman: self.has_double_huffman(),
+        }
+    }
+}
+
+/// A field of the encoded header, from `ArchiveHeader::field_ranges`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeaderField {
+    Magic,
+    Version,
+    Flags,
+    TreeLen,
+    DataLen,
+    Checksum,
+    EntryCount,
+    LeafWidth,
+    PrePasses,
+    /// The u16 length in front of the comment
+    CommentLen,
+    Comment,
+    Dictionary,
+    CrlfCount,
+    PayloadLen,
+    PayloadChecksum,
+    FirstPassLen,
+    ExtendedFlags,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockType {
+    /// The whole input is coded with a single tree
+    Single,
+    /// The input is kept as-is, for data that huffman coding would only grow
+    Stored,
+    /// Records share one tree and are indexed for random access
+    Records,
+    /// Records holding the paths and contents of files
+    Files,
+    /// Blocks are coded independently, each with its own tree
+    Blocks,
+    /// Blocks holding separate streams, e.g. the literals and lengths of an LZ factorization
+    Streams,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChecksumKind {
+    None,
+    /// CRC-32 (IEEE) of the original data
+    Crc32,
+}
+
+/// Byte order of the multi-byte header fields
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endianness {
+    Big,
+}
+
+/// Everything needed to know how an archive must be decoded, read from the header alone
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveDescription {
+    pub version: u8,
+    pub block_type: BlockType,
+    pub checksum: ChecksumKind,
+    pub endianness: Endianness,
+    /// Bits used for each leaf symbol in the serialized tree
+    pub leaf_width: u8,
+    /// The tree is not stored and has to be provided to a `Decompressor`
+    pub preset_tree: bool,
+    pub pre_passes_applied: bool,
+    /// The pre-passes to undo after decoding, in the order they were applied. Unknown ones
+    /// already fail parsing with `DecompressError::UnsupportedPrePass`.
+    pub pre_passes: Vec<PrePass>,
+    pub comment: Option<String>,
+    /// Fingerprint of the dictionary the archive is coded with
+    pub dictionary: Option<u64>,
+    /// Line endings were CRLF and are restored on decoding
+    pub crlf_line_endings: bool,
+    /// A CRC-32 of the compressed bytes can be checked before decoding
+    pub payload_checksum: bool,
+    /// The payload was huffman coded twice
+    pub double_huffman: bool,
+}
+
+impl fmt::Display for ArchiveDescription {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let yes_no = |flag: bool| if flag { "yes" } else { "no" };
+        writeln!(f, "version:     {}", self.version)?;
+        writeln!(f, "block type:  {:?}", self.block_type)?;
+        writeln!(f, "checksum:    {:?}", self.checksum)?;
+        writeln!(f, "endianness:  {:?}", self.endianness)?;
+        writeln!(f, "leaf width:  {}", self.leaf_width)?;
+        writeln!(f, "preset tree: {}", yes_no(self.preset_tree))?;
+        if self.pre_passes.is_empty() {
+            write!(f, "pre-passes:  no")?;
+        } else {
+            let names: Vec<String> = self
+                .pre_passes
+                .iter()
+                .map(|pass| format!("{pass:?}"))
+                .collect();
+            write!(f, "pre-passes:  {}", names.join(", "))?;
+        }
+        if let Some(comment) = &self.comment {
+            write!(f, "\ncomment:     {comment}")?;
+        }
+        if let Some(dictionary) = self.dictionary {
+            write!(f, "\ndictionary:  {dictionary:016x}")?;
+        }
+        if self.crlf_line_endings {
+            write!(f, "\nline ends:   CRLF")?;
+        }
+        if self.payload_checksum {
+            write!(f, "\npayload crc: yes")?;
+        }
+        if self.double_huffman {
+            write!(f, "\ndouble pass: yes")?;
+        }
+        Ok(())
+    }
+}
+
+/// Where the regions of an archive start, in bits from the start of the archive
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ArchiveLayout {
+    /// First bit of the tree, right after the header and any index
+    pub tree_start: usize,
+    /// First bit of the payload, after the tree has been read. Equal to `tree_start` when the
+    /// archive embeds no tree: stored and preset-tree archives, and block archives, whose frames
+    /// each carry their own tree.
+    pub payload_start: usize,
+}
@@ -0,0 +1,57 @@
+//! Compressing data using huffman coding
+
+#[cfg(feature = "test-alloc")]
+mod alloc;
+mod archive;
+mod blocks;
+#[cfg(feature = "test-util")]
+mod builder;
+mod canonical;
+mod checksum;
+mod compressor;
+mod decode;
+mod decompressor;
+mod dictionary;
+mod double;
+mod error;
+mod files;
+mod frame;
+mod header;
+mod prepass;
+mod records;
+#[cfg(feature = "stats")]
+mod stats;
+mod stream;
+mod streams;
+mod text;
+mod tree;
+mod zipf;
+
+#[cfg(feature = "test-alloc")]
+pub use alloc::CountingAllocator;
+pub use archive::{HuffmanArchive, Options};
+#[cfg(feature = "test-util")]
+pub use builder::ArchiveBuilder;
+pub use canonical::{
+    canonical_codes, code_lengths_from_frequencies, deserialize_code_length_table,
+    deserialize_code_lengths_rle, serialize_code_length_table, serialize_code_lengths_rle,
+};
+pub use compressor::Compressor;
+pub use decode::DecodeTable;
+pub use decompressor::Decompressor;
+pub use dictionary::DictMode;
+pub use error::{CompressError, DecompressError};
+pub use frame::{ArchiveLog, FrameEncoder, encode_frame};
+pub use header::{
+    ArchiveDescription, ArchiveHeader, ArchiveLayout, BlockType, ChecksumKind,
+    EXTENDED_FLAG_LENGTH_TABLE, Endianness, FLAG_BLOCKS, FLAG_CHECKSUM, FLAG_CODE_LENGTHS,
+    FLAG_COMMENT, FLAG_CRLF, FLAG_DICTIONARY, FLAG_DOUBLE_HUFFMAN, FLAG_EXTENDED, FLAG_FILES,
+    FLAG_LEAF_WIDTH, FLAG_PAYLOAD_CHECKSUM, FLAG_PRE_PASSES, FLAG_PRESET_TREE, FLAG_RECORDS,
+    FLAG_STORED, FLAG_STREAMS, FORMAT_VERSION, HeaderField, MAGIC, SUPPORTED_FORMAT_VERSIONS,
+};
+pub use prepass::PrePass;
+#[cfg(feature = "stats")]
+pub use stats::Stats;
+pub use stream::{PuhlDecoder, PuhlEncoder};
+pub use tree::{HuffmanNode, HuffmanTree, TreeCosts, codebook_diff};
+pub use zipf::{ZipfFit, analyze_zipf};
@@ -1,270 +1,598 @@
 use bitvec::prelude::*;
-use std::cmp::Ordering;
-use std::collections::BinaryHeap;
-use std::io::{self, Read};
-
-#[derive(Eq)]
-pub struct HuffmanNode {
-    frequency: usize,
-    byte: Option<u8>,
-    l_child: Option<Box<HuffmanNode>>,
-    r_child: Option<Box<HuffmanNode>>,
+use compression::{
+    ArchiveHeader, Compressor, DecompressError, DictMode, FrameEncoder, HuffmanArchive,
+    HuffmanTree, Options, PrePass, encode_frame,
+};
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+#[derive(Default)]
+struct Args {
+    decompress: bool,
+    info: bool,
+    verify: bool,
+    no_checksum: bool,
+    payload_checksum: bool,
+    store: bool,
+    best: bool,
+    text: bool,
+    double: bool,
+    quiet: bool,
+    split: Option<usize>,
+    block_size: Option<usize>,
+    chunk: Option<usize>,
+    threads: usize,
+    comment: Option<String>,
+    dict_mode: Option<DictMode>,
+    dict: Option<String>,
+    directory: Option<String>,
+    input: Option<String>,
+    // Set instead of `input` when more than one file is given
+    batch: Vec<String>,
 }
 
-impl PartialEq for HuffmanNode {
-    fn eq(&self, other: &Self) -> bool {
-        self.frequency == other.frequency && self.byte == other.byte
-    }
-}
+impl Args {
+    fn parse() -> Result<Self, String> {
+        let mut args = Args::default();
+        let mut inputs = Vec::new();
+        let mut iter = std::env::args().skip(1);
+
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "-d" => args.decompress = true,
+                "--info" => args.info = true,
+                "--verify" => args.verify = true,
+                "--no-checksum" => args.no_checksum = true,
+                "--payload-checksum" => args.payload_checksum = true,
+                "--store" => args.store = true,
+                "-9" => args.best = true,
+                "--text" => args.text = true,
+                "--double" => args.double = true,
+                "-q" => args.quiet = true,
+                "--split" => {
+                    let size = iter.next().ok_or("--split needs a size")?;
+                    let size = parse_size(&size)?;
+                    if size == 0 {
+                        return Err("--split needs a size of at least one byte".into());
+                    }
+                    args.split = Some(size);
+                }
+                "--blocks" => {
+                    let size = iter.next().ok_or("--blocks needs a size")?;
+                    let size = parse_size(&size)?;
+                    if size == 0 {
+                        return Err("--blocks needs a size of at least one byte".into());
+                    }
+                    args.block_size = Some(size);
+                }
+                "--chunk" => {
+                    let size = iter.next().ok_or("--chunk needs a size")?;
+                    let size = parse_size(&size)?;
+                    if size == 0 {
+                        return Err("--chunk needs a size of at least one byte".into());
+                    }
+                    args.chunk = Some(size);
+                }
+                "--threads" => {
+                    let threads = iter.next().ok_or("--threads needs a count")?;
+                    args.threads = threads
+                        .parse()
+                        .map_err(|_| format!("invalid thread count {threads}"))?;
+                }
+                "--dict-mode" => {
+                    args.dict_mode = match iter.next().as_deref() {
+                        Some("embed") => Some(DictMode::Embed),
+                        Some("reference") => Some(DictMode::Reference),
+                        _ => return Err("--dict-mode needs embed or reference".into()),
+                    };
+                }
+                "-C" => args.directory = Some(iter.next().ok_or("-C needs a directory")?),
+                "--dict" => args.dict = Some(iter.next().ok_or("--dict needs an archive")?),
+                "--comment" => {
+                    let comment = iter.next().ok_or("--comment needs a text")?;
+                    if comment.len() > ArchiveHeader::MAX_COMMENT_LEN {
+                        return Err(format!(
+                            "--comment is limited to {} bytes",
+                            ArchiveHeader::MAX_COMMENT_LEN
+                        ));
+                    }
+                    args.comment = Some(comment);
+                }
+                flag if flag.starts_with('-') => return Err(format!("unknown option {flag}")),
+                _ => inputs.push(arg),
+            }
+        }
 
-// Reverse the order so the the huffman heap becomes a min heap
-impl Ord for HuffmanNode {
-    fn cmp(&self, other: &Self) -> Ordering {
-        match other.frequency.cmp(&self.frequency) {
-            Ordering::Equal => self.byte.cmp(&other.byte),
-            ord => ord,
+        if inputs.len() > 1 {
+            if !args.is_plain_compression() {
+                return Err("several input files can only be compressed without a mode".into());
+            }
+            args.batch = inputs;
+        } else {
+            args.input = inputs.pop();
         }
-    }
-}
 
-impl PartialOrd for HuffmanNode {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
+        Ok(args)
     }
-}
 
-impl HuffmanNode {
-    fn new_leaf(frequency: usize, byte: u8) -> Self {
-        Self {
-            frequency,
-            byte: Some(byte),
-            l_child: None,
-            r_child: None,
-        }
+    // Options that pick another way to compress, or to not compress at all, rule out a batch
+    fn is_plain_compression(&self) -> bool {
+        !(self.decompress
+            || self.info
+            || self.verify
+            || self.store
+            || self.best
+            || self.text
+            || self.double
+            || self.split.is_some()
+            || self.block_size.is_some()
+            || self.chunk.is_some()
+            || self.dict_mode.is_some()
+            || self.dict.is_some())
     }
 
-    fn new_internal(left: HuffmanNode, right: HuffmanNode) -> Self {
-        Self {
-            frequency: left.frequency + right.frequency,
-            byte: None,
-            l_child: Some(Box::new(left)),
-            r_child: Some(Box::new(right)),
+    fn options(&self) -> Options {
+        Options {
+            checksum: !self.no_checksum,
+            comment: self.comment.clone(),
+            threads: self.threads,
+            payload_checksum: self.payload_checksum,
+            ..Options::default()
         }
     }
 }
 
-pub struct HuffmanTree {
-    root: Box<HuffmanNode>,
+// Accepts plain byte counts or a K/M/G suffix, e.g. "10M"
+fn parse_size(size: &str) -> Result<usize, String> {
+    let (digits, multiplier) = match size.chars().last() {
+        Some('K' | 'k') => (&size[..size.len() - 1], 1 << 10),
+        Some('M' | 'm') => (&size[..size.len() - 1], 1 << 20),
+        Some('G' | 'g') => (&size[..size.len() - 1], 1 << 30),
+        _ => (size, 1),
+    };
+
+    let value: usize = digits.parse().map_err(|_| format!("invalid size {size}"))?;
+    value
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("size {size} is too large"))
 }
 
-impl HuffmanTree {
-    pub fn build(data: &[u8]) -> Self {
-        let mut frequency = [0usize; 256];
-        for &byte in data {
-            frequency[byte as usize] += 1;
-        }
+fn volume_path(base: &str, index: usize) -> String {
+    format!("{base}.{index:03}")
+}
 
-        let mut heap: BinaryHeap<HuffmanNode> = frequency
-            .iter()
-            .enumerate()
-            .filter(|&(_, &freq)| freq != 0)
-            .map(|(byte, &freq)| HuffmanNode::new_leaf(freq, byte as u8))
-            .collect();
+// With `volumes`, reading "file.jp.001" also picks up "file.jp.002", "file.jp.003", ... while
+// they exist. Other files ending in ".001" are read alone.
+fn read_input(input: Option<&str>, volumes: bool) -> io::Result<Vec<u8>> {
+    let mut buffer = Vec::new();
 
-        // If the file is empty, make a dummy node
-        if heap.is_empty() {
-            heap.push(HuffmanNode::new_leaf(0, 0)); 
+    match input {
+        None => {
+            io::stdin().read_to_end(&mut buffer)?;
         }
+        Some(path) => match path
+            .strip_suffix(".001")
+            .filter(|base| volumes && base.ends_with(".jp"))
+        {
+            Some(base) => {
+                let mut index = 1;
+                while Path::new(&volume_path(base, index)).exists() {
+                    buffer.extend(fs::read(volume_path(base, index))?);
+                    index += 1;
+                }
+            }
+            None => buffer = fs::read(path)?,
+        },
+    }
 
-        // Edge case where data only has one unique character
-        if heap.len() == 1 {
-            let single_node = heap.pop().unwrap();
-            let dummy_node = HuffmanNode::new_leaf(0, 0);
-            heap.push(HuffmanNode::new_internal(single_node, dummy_node));
-        }
+    Ok(buffer)
+}
 
-        while heap.len() > 1 {
-            let left = heap.pop().unwrap();
-            let right = heap.pop().unwrap();
-            heap.push(HuffmanNode::new_internal(left, right));
+// Every volume is a self-contained frame no larger than `limit` bytes
+fn write_volumes(data: &[u8], base: &str, limit: usize, options: &Options) -> Result<(), String> {
+    let mut rest = data;
+    let mut index = 1;
+    // The share of `chunk_len` bytes that fits the volume when they code to `frame_len`
+    let scale = |chunk_len: usize, frame_len: usize| {
+        chunk_len
+            .checked_mul(limit)
+            .map(|product| product / frame_len)
+            .ok_or_else(|| format!("--split {limit} is too large"))
+    };
+
+    while !rest.is_empty() {
+        let mut chunk_len = rest.len().min(limit);
+        let mut frame = encode_frame(&rest[..chunk_len], options);
+
+        // Input usually shrinks, so guess how much more of it fills the volume
+        if frame.len() < limit && chunk_len < rest.len() {
+            chunk_len = scale(chunk_len, frame.len())?.min(rest.len());
+            frame = encode_frame(&rest[..chunk_len], options);
         }
 
-        Self {
-            root: Box::new(heap.pop().unwrap()),
+        while frame.len() > limit {
+            if chunk_len == 1 {
+                return Err(format!(
+                    "--split {limit} is too small to hold a single frame"
+                ));
+            }
+            chunk_len = scale(chunk_len, frame.len())?.clamp(1, chunk_len - 1);
+            frame = encode_frame(&rest[..chunk_len], options);
         }
-    }
 
-    pub fn serialize(&self) -> BitVec<u8, Msb0> {
-        let mut bitstream = BitVec::<u8, Msb0>::new();
-        Self::serialize_recursive(&self.root, &mut bitstream);
-        bitstream
+        fs::write(volume_path(base, index), frame).map_err(|err| err.to_string())?;
+        rest = &rest[chunk_len..];
+        index += 1;
     }
 
-    pub fn get_encoding_table(&self) -> Vec<BitVec<u8, Msb0>> {
-        let mut table = vec![BitVec::<u8, Msb0>::new(); 256];
-        Self::build_table_recursive(&self.root, &mut table, &mut BitVec::new());
-        table
+    // A longer earlier split would leave volumes that get read back as part of this one
+    while Path::new(&volume_path(base, index)).exists() {
+        fs::remove_file(volume_path(base, index)).map_err(|err| err.to_string())?;
+        index += 1;
     }
 
-    fn serialize_recursive(node: &HuffmanNode, bitstream: &mut BitVec<u8, Msb0>) {
-        if let Some(byte) = node.byte {
-            bitstream.push(true);
-            bitstream.extend_from_bitslice(byte.view_bits::<Msb0>());
+    Ok(())
+}
+
+// Compresses every input to `<input>.jp` with one compressor, so its buffer is reused. Empty
+// inputs give empty outputs, as they do when compressed one at a time.
+fn compress_batch(inputs: &[String], options: &Options, quiet: bool) -> Result<(), String> {
+    let mut compressor = Compressor::with_options(options.clone());
+    let (mut original_len, mut compressed_len) = (0, 0);
+
+    for input in inputs {
+        let data = read_input(Some(input), false)
+            .map_err(|err| format!("could not read {input}: {err}"))?;
+        let archive = if data.is_empty() {
+            &[]
         } else {
-            bitstream.push(false);
-            if let Some(ref left) = node.l_child {
-                Self::serialize_recursive(left, bitstream);
-            }
-            if let Some(ref right) = node.r_child {
-                Self::serialize_recursive(right, bitstream);
-            }
-        }
+            compressor.compress(&data)
+        };
+
+        let output = format!("{input}.jp");
+        fs::write(&output, archive).map_err(|err| format!("could not write {output}: {err}"))?;
+        original_len += data.len() as i64;
+        compressed_len += archive.len() as i64;
     }
 
-    fn build_table_recursive(
-        node: &HuffmanNode,
-        table: &mut [BitVec<u8, Msb0>],
-        current_encoding: &mut BitVec<u8, Msb0>,
-    ) {
-        if let Some(byte) = node.byte {
-            table[byte as usize] = current_encoding.clone();
-        } else {
-            if let Some(ref left) = node.l_child {
-                current_encoding.push(false);
-                Self::build_table_recursive(left, table, current_encoding);
-                current_encoding.pop();
-            }
-            if let Some(ref right) = node.r_child {
-                current_encoding.push(true);
-                Self::build_table_recursive(right, table, current_encoding);
-                current_encoding.pop();
-            }
+    if !quiet {
+        eprintln!(
+            "{} files, {original_len} bytes compressed to {compressed_len} bytes, {} bytes saved",
+            inputs.len(),
+            original_len - compressed_len
+        );
+    }
+
+    Ok(())
+}
+
+fn open_input(input: Option<&str>) -> io::Result<Box<dyn Read>> {
+    Ok(match input {
+        Some(path) => Box::new(fs::File::open(path)?),
+        None => Box::new(io::stdin().lock()),
+    })
+}
+
+// Reads into `buffer`, returning as soon as any bytes have arrived
+fn read_some(input: &mut impl Read, buffer: &mut [u8]) -> io::Result<usize> {
+    loop {
+        match input.read(buffer) {
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            result => return result,
         }
     }
+}
 
-    pub fn deserialize_shape(bit_iter: &mut impl Iterator<Item = bool>) -> Self {
-        Self {
-            root: Box::new(Self::deserialize_recursive(bit_iter)),
+// Every read becomes a frame of its own that is written out right away, so the output keeps
+// up with a stream that may never end
+fn compress_chunked(mut input: impl Read, chunk_size: usize, options: &Options) -> io::Result<()> {
+    let mut encoder = FrameEncoder::with_options(io::stdout().lock(), chunk_size, options.clone());
+    let mut buffer = vec![0; chunk_size];
+    loop {
+        let read = read_some(&mut input, &mut buffer)?;
+        if read == 0 {
+            break;
         }
+        encoder.write_all(&buffer[..read])?;
+        encoder.flush()?;
     }
+    encoder.finish().map(drop)
+}
 
-    fn deserialize_recursive(bit_iter: &mut impl Iterator<Item = bool>) -> HuffmanNode {
-        let is_leaf = bit_iter.next().expect("Something wrong with bitstream");
-        
-        if is_leaf {
-            let mut byte = 0u8;
-            for i in 0..8 {
-                if bit_iter.next().unwrap() {
-                    byte |= 1 << (7 - i); 
+// Decodes frames as soon as they have fully arrived, reading `chunk_size` bytes at a time
+fn decompress_chunked(mut input: impl Read, chunk_size: usize) -> io::Result<()> {
+    let mut out = io::stdout().lock();
+    let mut pending = Vec::new();
+    let mut buffer = vec![0; chunk_size];
+    loop {
+        let read = read_some(&mut input, &mut buffer)?;
+        pending.extend_from_slice(&buffer[..read]);
+
+        while !pending.is_empty() {
+            match HuffmanArchive::decompress_first_frame(&pending) {
+                Ok((frame, consumed)) => {
+                    out.write_all(&frame)?;
+                    pending.drain(..consumed);
                 }
+                Err(
+                    DecompressError::TooShort
+                    | DecompressError::TruncatedTree
+                    | DecompressError::TruncatedPayload,
+                ) if read > 0 => break,
+                Err(err) => return Err(io::Error::new(io::ErrorKind::InvalidData, err)),
             }
-            HuffmanNode::new_leaf(0, byte)
-        } else {
-            let left = Self::deserialize_recursive(bit_iter);
-            let right = Self::deserialize_recursive(bit_iter);
-            HuffmanNode::new_internal(left, right)
+        }
+        out.flush()?;
+
+        if read == 0 {
+            return Ok(());
         }
     }
 }
 
-pub struct HuffmanArchive;
+// The dictionary is the tree embedded in an earlier archive
+fn read_dictionary(path: &str) -> Result<HuffmanTree, String> {
+    let archive = fs::read(path).map_err(|err| format!("could not read {path}: {err}"))?;
+    HuffmanArchive::extract_tree(&archive).map_err(|err| format!("no dictionary in {path}: {err}"))
+}
+
+// Without --dict, embed mode makes a dictionary of the input's own tree
+fn compress_with_dictionary(
+    data: &[u8],
+    mode: DictMode,
+    dict: Option<&str>,
+    options: &Options,
+) -> Result<BitVec<u8, Msb0>, String> {
+    let dictionary = match (dict, mode) {
+        (Some(path), _) => read_dictionary(path)?,
+        (None, DictMode::Embed) => HuffmanTree::build(data),
+        (None, DictMode::Reference) => return Err("--dict-mode reference needs --dict".into()),
+    };
+    HuffmanArchive::compress_with_dictionary(data, &dictionary, mode, options)
+        .map_err(|err| err.to_string())
+}
 
-impl HuffmanArchive {
-    pub fn compress(data: &[u8], tree: &HuffmanTree) -> BitVec<u8, Msb0> {
-        let tree_bits = tree.serialize();
-        let encoding_table = tree.get_encoding_table();
+// Every regular file under `dir`, with its path relative to `root`. Symlinks are skipped so a
+// link cycle cannot make the walk endless.
+fn collect_files(root: &Path, dir: &Path, files: &mut Vec<(String, Vec<u8>)>) -> io::Result<()> {
+    let mut entries = fs::read_dir(dir)?.collect::<io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        let path = entry.path();
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            collect_files(root, &path, files)?;
+        } else if file_type.is_file() {
+            let relative = path.strip_prefix(root).unwrap();
+            let name = relative
+                .iter()
+                .map(|part| part.to_str())
+                .collect::<Option<Vec<_>>>()
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("{} is not valid UTF-8", path.display()),
+                    )
+                })?
+                .join("/");
+            files.push((name, fs::read(&path)?));
+        }
+    }
 
-        let tree_len = tree_bits.len() as u64;
-        let data_len = data.len() as u64;
+    Ok(())
+}
 
-        let mut archive = BitVec::<u8, Msb0>::new();
-        
-        archive.extend_from_bitslice(b"JP".view_bits::<Msb0>());
-        
-        archive.extend_from_bitslice(tree_len.to_be_bytes().view_bits::<Msb0>());
-        archive.extend_from_bitslice(data_len.to_be_bytes().view_bits::<Msb0>());
-        
-        archive.extend_from_bitslice(&tree_bits);
+fn compress_directory(dir: &Path, options: &Options) -> io::Result<BitVec<u8, Msb0>> {
+    let mut files = Vec::new();
+    collect_files(dir, dir, &mut files)?;
+    let files: Vec<(&str, &[u8])> = files
+        .iter()
+        .map(|(name, contents)| (name.as_str(), contents.as_slice()))
+        .collect();
+    Ok(HuffmanArchive::compress_files(&files, options))
+}
 
-        for &byte in data {
-            archive.extend_from_bitslice(&encoding_table[byte as usize]);
-        }
+// Transport layers sometimes gzip whatever passes through them, unwrap that first
+#[cfg(feature = "gzip")]
+fn inflate_if_gzip(input: Vec<u8>) -> io::Result<Vec<u8>> {
+    const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
 
-        archive
+    if !input.starts_with(&GZIP_MAGIC) {
+        return Ok(input);
     }
 
-    pub fn decompress(archive_bytes: &[u8]) -> Option<Vec<u8>> {
-        if archive_bytes.len() < 2 {
-            eprintln!("Error: File too small.");
-            return None;
-        }
+    let mut inflated = Vec::new();
+    flate2::read::MultiGzDecoder::new(&input[..]).read_to_end(&mut inflated)?;
+    Ok(inflated)
+}
 
-        if &archive_bytes[0..2] != b"JP" {
-            eprintln!("Error: Invalid magic number.");
-            return None;
+// Like other Unix tools, a reader that goes away (e.g. `| head`) ends the program quietly
+fn write_stdout(bytes: &[u8]) {
+    let mut out = io::stdout().lock();
+    match out.write_all(bytes).and_then(|()| out.flush()) {
+        Ok(()) => {}
+        Err(err) if err.kind() == io::ErrorKind::BrokenPipe => std::process::exit(0),
+        Err(err) => {
+            eprintln!("Error: could not write output: {err}.");
+            std::process::exit(1);
         }
+    }
+}
 
-        let _tree_len = u64::from_be_bytes(archive_bytes[2..10].try_into().unwrap());
-        let data_len = u64::from_be_bytes(archive_bytes[10..18].try_into().unwrap());
-
-        let bits = archive_bytes.view_bits::<Msb0>();
-        let mut bit_iter = bits[144..].iter().by_vals();
-
-        let tree = HuffmanTree::deserialize_shape(&mut bit_iter);
+fn main() {
+    let args = match Args::parse() {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("Error: {err}.");
+            std::process::exit(2);
+        }
+    };
+    let options = args.options();
 
-        let mut output = Vec::with_capacity(data_len as usize);
-        let mut current_node = &*tree.root;
+    if !args.batch.is_empty() {
+        if let Err(err) = compress_batch(&args.batch, &options, args.quiet) {
+            eprintln!("Error: {err}.");
+            std::process::exit(1);
+        }
+        return;
+    }
 
-        while output.len() < data_len as usize {
-            let bit = bit_iter.next().expect("Ran out of bits before reaching data_len");
+    if let Some(dir) = args
+        .input
+        .as_deref()
+        .filter(|input| Path::new(input).is_dir())
+    {
+        // Directories are only packed, other modes would read them as a file
+        if !args.is_plain_compression() {
+            eprintln!("Error: {dir} is a directory, which can only be compressed without a mode.");
+            std::process::exit(2);
+        }
+        match compress_directory(Path::new(dir), &options) {
+            Ok(archive) => write_stdout(archive.as_raw_slice()),
+            Err(err) => {
+                eprintln!("Error: could not pack {dir}: {err}.");
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
 
-            if !bit {
-                current_node = current_node.l_child.as_deref().unwrap();
+    if let Some(chunk_size) = args.chunk {
+        let result = open_input(args.input.as_deref()).and_then(|input| {
+            if args.decompress {
+                decompress_chunked(input, chunk_size)
             } else {
-                current_node = current_node.r_child.as_deref().unwrap();
+                compress_chunked(input, chunk_size, &options)
             }
-
-            if let Some(byte) = current_node.byte {
-                output.push(byte);
-                current_node = &*tree.root; 
+        });
+        match result {
+            Ok(()) => {}
+            Err(err) if err.kind() == io::ErrorKind::BrokenPipe => {}
+            Err(err) => {
+                eprintln!("Error: {err}.");
+                std::process::exit(1);
             }
         }
-
-        Some(output)
+        return;
     }
-}
 
-fn main() {
-    let args: Vec<String> = std::env::args().collect();
-    let decompress_mode = args.contains(&"-d".to_string());
-
-    let mut input_buffer = Vec::new();
-    io::stdin()
-        .read_to_end(&mut input_buffer)
-        .expect("Failed to read from stdin");
+    let input_buffer = match read_input(args.input.as_deref(), args.decompress) {
+        Ok(buffer) => buffer,
+        Err(err) => {
+            eprintln!("Error: could not read input: {err}.");
+            std::process::exit(1);
+        }
+    };
 
     if input_buffer.is_empty() {
         return;
     }
 
-    if decompress_mode {
-        match HuffmanArchive::decompress(&input_buffer) {
-            Some(original_data) => {
-                let mut out = io::stdout().lock();
-                io::Write::write_all(&mut out, &original_data).expect("Failed to write to stdout");
+    if args.info {
+        match HuffmanArchive::describe(&input_buffer) {
+            Ok(description) => {
+                let decoded_len = HuffmanArchive::decoded_len(&input_buffer).unwrap();
+                write_stdout(
+                    format!("{description}\noriginal:    {decoded_len} bytes\n").as_bytes(),
+                );
             }
-            None => {
-                eprintln!("Error: Could not decompress.");
+            Err(err) => {
+                eprintln!("Error: {err}.");
                 std::process::exit(1);
             }
         }
+    } else if args.verify {
+        match HuffmanArchive::validate(&input_buffer) {
+            Ok(()) => write_stdout(b"OK\n"),
+            Err(err) => {
+                eprintln!("Error: {err}.");
+                std::process::exit(1);
+            }
+        }
+    } else if args.decompress {
+        #[cfg(feature = "gzip")]
+        let input_buffer = match inflate_if_gzip(input_buffer) {
+            Ok(buffer) => buffer,
+            Err(err) => {
+                eprintln!("Error: could not inflate gzip input: {err}.");
+                std::process::exit(1);
+            }
+        };
+
+        if ArchiveHeader::parse(&input_buffer).is_ok_and(|header| header.has_files()) {
+            let target = args.directory.as_deref().unwrap_or(".");
+            if let Err(err) = HuffmanArchive::extract_files(&input_buffer, Path::new(target)) {
+                eprintln!("Error: could not extract: {err}.");
+                std::process::exit(1);
+            }
+            return;
+        }
+
+        let decompressed = match &args.dict {
+            Some(path) => read_dictionary(path).and_then(|dictionary| {
+                HuffmanArchive::decompress_with_dictionary(&input_buffer, &dictionary)
+                    .map_err(|err| err.to_string())
+            }),
+            None => HuffmanArchive::decompress_frames(&input_buffer).map_err(|err| err.to_string()),
+        };
+
+        match decompressed {
+            Ok(original_data) => write_stdout(&original_data),
+            Err(err) => {
+                eprintln!("Error: {err}.");
+                std::process::exit(1);
+            }
+        }
+    } else if let Some(limit) = args.split {
+        let Some(input) = &args.input else {
+            eprintln!("Error: --split needs an input file to name the volumes after.");
+            std::process::exit(2);
+        };
+
+        if let Err(err) = write_volumes(&input_buffer, &format!("{input}.jp"), limit, &options) {
+            eprintln!("Error: {err}.");
+            std::process::exit(1);
+        }
     } else {
-        let tree = HuffmanTree::build(&input_buffer);
-        let compressed_bitvec = HuffmanArchive::compress(&input_buffer, &tree);
-        
+        let compressed_bitvec = if args.store {
+            HuffmanArchive::store(&input_buffer, &options)
+        } else if args.best {
+            HuffmanArchive::compress_exhaustive(&input_buffer, &PrePass::ALL, &options)
+        } else if args.text {
+            HuffmanArchive::compress_text(&input_buffer, &options)
+        } else if args.double {
+            HuffmanArchive::compress_double(&input_buffer, &options)
+        } else if let Some(mode) = args.dict_mode {
+            match compress_with_dictionary(&input_buffer, mode, args.dict.as_deref(), &options) {
+                Ok(archive) => archive,
+                Err(err) => {
+                    eprintln!("Error: {err}.");
+                    std::process::exit(1);
+                }
+            }
+        } else if let Some(block_size) = args.block_size {
+            HuffmanArchive::compress_blocks(&input_buffer, block_size, &options)
+        } else {
+            let tree = HuffmanTree::build(&input_buffer);
+            HuffmanArchive::compress_with_options(&input_buffer, &tree, &options)
+        };
+
         let compressed_bytes = compressed_bitvec.as_raw_slice();
-        
-        let mut out = io::stdout().lock();
-        io::Write::write_all(&mut out, compressed_bytes).expect("Failed to write to stdout");
+
+        // `--store` only stands in for plain coding, the other modes were asked for by name
+        // and -9 already stores the input when that is smaller
+        if args.is_plain_compression() && !args.quiet && compressed_bytes.len() > input_buffer.len()
+        {
+            eprintln!(
+                "Warning: compressed output ({} bytes) is larger than the input ({} bytes), \
+                 consider --store.",
+                compressed_bytes.len(),
+                input_buffer.len()
+            );
+        }
+
+        write_stdout(compressed_bytes);
     }
-}
\ No newline at end of file
+}
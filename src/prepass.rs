@@ -0,0 +1,165 @@
+use bitvec::prelude::*;
+
+use crate::archive::TreeEncoding;
+use crate::{FLAG_PRE_PASSES, HuffmanArchive, HuffmanTree, Options};
+
+/// Reversible transforms run over the input before it is huffman coded. The archive records
+/// which ones were applied as a bit set, they run in bit order and are undone in reverse.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrePass {
+    /// Each byte is replaced with its difference to the previous one
+    Delta,
+    /// Each byte is replaced with its position in a list of recently seen bytes
+    MoveToFront,
+}
+
+impl PrePass {
+    pub const ALL: [PrePass; 2] = [PrePass::Delta, PrePass::MoveToFront];
+    /// Bits of every pre-pass this version can undo
+    pub(crate) const KNOWN: u8 = (1 << 0) | (1 << 1);
+
+    pub fn bit(self) -> u8 {
+        match self {
+            PrePass::Delta => 1 << 0,
+            PrePass::MoveToFront => 1 << 1,
+        }
+    }
+
+    pub(crate) fn apply(self, data: &mut [u8]) {
+        match self {
+            PrePass::Delta => {
+                let mut previous = 0u8;
+                for byte in data {
+                    (*byte, previous) = (byte.wrapping_sub(previous), *byte);
+                }
+            }
+            PrePass::MoveToFront => {
+                let mut recent: [u8; 256] = std::array::from_fn(|i| i as u8);
+                for byte in data {
+                    let position = recent.iter().position(|&b| b == *byte).unwrap();
+                    recent[..=position].rotate_right(1);
+                    *byte = position as u8;
+                }
+            }
+        }
+    }
+
+    pub(crate) fn undo(self, data: &mut [u8]) {
+        match self {
+            PrePass::Delta => {
+                let mut previous = 0u8;
+                for byte in data {
+                    *byte = byte.wrapping_add(previous);
+                    previous = *byte;
+                }
+            }
+            PrePass::MoveToFront => {
+                let mut recent: [u8; 256] = std::array::from_fn(|i| i as u8);
+                for byte in data {
+                    let position = *byte as usize;
+                    *byte = recent[position];
+                    recent[..=position].rotate_right(1);
+                }
+            }
+        }
+    }
+}
+
+fn mask_of(pre_passes: &[PrePass]) -> u8 {
+    pre_passes.iter().fold(0, |mask, pass| mask | pass.bit())
+}
+
+/// Every pre-pass set in `mask`, in the order they are applied
+pub(crate) fn pre_passes_in(mask: u8) -> impl DoubleEndedIterator<Item = PrePass> {
+    PrePass::ALL
+        .into_iter()
+        .filter(move |pass| mask & pass.bit() != 0)
+}
+
+impl HuffmanArchive {
+    /// Huffman codes `data` after running `pre_passes` over it, the checksum still covers
+    /// the original bytes
+    pub fn compress_with_pre_passes(
+        data: &[u8],
+        pre_passes: &[PrePass],
+        options: &Options,
+    ) -> BitVec<u8, Msb0> {
+        Self::compress_candidate(data, mask_of(pre_passes), false, options)
+    }
+
+    /// Whichever is smaller of huffman coding `data` and storing it as-is
+    pub fn compress_best(data: &[u8], options: &Options) -> BitVec<u8, Msb0> {
+        let tree = HuffmanTree::build(data);
+        let coded = Self::compress_with_options(data, &tree, options);
+        let stored = Self::store(data, options);
+        if stored.len() < coded.len() {
+            stored
+        } else {
+            coded
+        }
+    }
+
+    /// Tries every combination of `pre_passes`, with 8-bit and narrowest leaves, and keeps the
+    /// smallest archive. Costs one tree build and encode per candidate, so it is meant for the
+    /// highest compression level only. Never larger than `compress_best`.
+    pub fn compress_exhaustive(
+        data: &[u8],
+        pre_passes: &[PrePass],
+        options: &Options,
+    ) -> BitVec<u8, Msb0> {
+        let mut best = Self::compress_best(data, options);
+
+        let allowed = mask_of(pre_passes);
+        for mask in (0..=allowed).filter(|mask| mask & !allowed == 0) {
+            for narrow_leaves in [false, true] {
+                let candidate = Self::compress_candidate(data, mask, narrow_leaves, options);
+                if candidate.len() < best.len() {
+                    best = candidate;
+                }
+            }
+        }
+
+        best
+    }
+
+    fn compress_candidate(
+        data: &[u8],
+        mask: u8,
+        narrow_leaves: bool,
+        options: &Options,
+    ) -> BitVec<u8, Msb0> {
+        let mut transformed = data.to_vec();
+        for pass in pre_passes_in(mask) {
+            pass.apply(&mut transformed);
+        }
+
+        let tree = HuffmanTree::build(&transformed);
+        let leaf_width = tree.leaf_width();
+        let (tree_bits, tree_encoding, encoding_table) = if narrow_leaves && leaf_width != 8 {
+            let tree_bits = tree.serialize_with_leaf_width(leaf_width);
+            let encoding = TreeEncoding::LeafWidth(leaf_width);
+            (tree_bits, encoding, tree.get_encoding_table())
+        } else {
+            Self::smallest_tree_encoding(&tree)
+        };
+
+        let mut header = Self::new_header(tree_bits.len() as u64, data, options);
+        tree_encoding.mark(&mut header);
+        if mask != 0 {
+            header.flags |= FLAG_PRE_PASSES;
+            header.pre_passes = Some(mask);
+        }
+
+        let mut archive = BitVec::<u8, Msb0>::new();
+
+        header.write(&mut archive);
+
+        Self::write_tree(&header, &tree_bits, &mut archive);
+
+        Self::encode_payload(&transformed, &encoding_table, &mut archive);
+
+        Self::seal_payload_checksum(&mut archive, 0);
+
+        archive
+    }
+}
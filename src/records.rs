@@ -0,0 +1,108 @@
+use bitvec::prelude::*;
+
+use crate::{
+    ArchiveHeader, DecodeTable, DecompressError, FLAG_RECORDS, HuffmanArchive, HuffmanTree, Options,
+};
+
+impl HuffmanArchive {
+    /// Compresses many small records with one tree built over all of them, keeping an index
+    /// of where each record's codes start so `decompress_record` can decode any one of them.
+    /// `decompress` on the archive returns the records concatenated.
+    pub fn compress_records(records: &[&[u8]], options: &Options) -> BitVec<u8, Msb0> {
+        Self::compress_records_with_flags(records, FLAG_RECORDS, options)
+    }
+
+    /// `compress_records` with `flags` set instead of just `FLAG_RECORDS`
+    pub(crate) fn compress_records_with_flags(
+        records: &[&[u8]],
+        flags: u16,
+        options: &Options,
+    ) -> BitVec<u8, Msb0> {
+        let data = records.concat();
+        let tree = HuffmanTree::build(&data);
+        let tree_bits = tree.serialize();
+        let encoding_table = tree.get_encoding_table();
+
+        let mut header = Self::new_header(tree_bits.len() as u64, &data, options);
+        header.flags |= flags;
+        header.entry_count = Some(records.len() as u64);
+
+        let mut payload = BitVec::<u8, Msb0>::new();
+        let mut index = BitVec::<u8, Msb0>::new();
+        for record in records {
+            index.extend_from_bitslice((payload.len() as u64).to_be_bytes().view_bits::<Msb0>());
+            index.extend_from_bitslice((record.len() as u64).to_be_bytes().view_bits::<Msb0>());
+            Self::encode_payload(record, &encoding_table, &mut payload);
+        }
+
+        let mut archive = BitVec::<u8, Msb0>::new();
+
+        header.write(&mut archive);
+
+        archive.extend_from_bitslice(&index);
+        Self::write_tree(&header, &tree_bits, &mut archive);
+        archive.extend_from_bitslice(&payload);
+
+        Self::seal_payload_checksum(&mut archive, 0);
+
+        archive
+    }
+
+    pub fn record_count(archive_bytes: &[u8]) -> Result<usize, DecompressError> {
+        let header = ArchiveHeader::parse(archive_bytes)?;
+        if !header.has_records() {
+            return Err(DecompressError::NotRecords);
+        }
+        header
+            .entry_count
+            .map(|count| count as usize)
+            .ok_or(DecompressError::NotRecords)
+    }
+
+    /// Decodes a single record without touching the others. The checksum covers all records
+    /// together, so it is not verified here.
+    pub fn decompress_record(
+        archive_bytes: &[u8],
+        index: usize,
+    ) -> Result<Vec<u8>, DecompressError> {
+        let header = ArchiveHeader::parse(archive_bytes)?;
+        if !header.has_records() {
+            return Err(DecompressError::NotRecords);
+        }
+        let count = header.entry_count.ok_or(DecompressError::NotRecords)? as usize;
+        if index >= count {
+            return Err(DecompressError::RecordOutOfRange { index, count });
+        }
+
+        let (bit_offset, record_len) = header.index_entry(archive_bytes, index)?;
+
+        let bits = archive_bytes.view_bits::<Msb0>();
+        let tree_start = header.body_offset() * 8;
+        let payload_start = tree_start + header.tree_len as usize;
+        let mut tree_iter = bits
+            .get(tree_start..payload_start)
+            .ok_or(DecompressError::TruncatedTree)?
+            .iter()
+            .by_vals();
+        let table = DecodeTable::new(&Self::read_tree(&header, &mut tree_iter)?)?;
+
+        // Every code is at least a bit long
+        if record_len
+            > bits
+                .len()
+                .saturating_sub(payload_start.saturating_add(bit_offset))
+        {
+            return Err(DecompressError::TruncatedPayload);
+        }
+
+        let mut output = vec![0; record_len];
+        table.decode(
+            archive_bytes,
+            payload_start + bit_offset,
+            &mut output,
+            &mut (),
+        )?;
+
+        Ok(output)
+    }
+}
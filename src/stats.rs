@@ -0,0 +1,28 @@
+use crate::decode::DecodeCounter;
+
+/// Counts of the work done by one `HuffmanArchive::decompress_with_stats` call
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    /// Steps from a node to one of its children, one per code bit. Symbols read from the
+    /// direct table count the steps a walk of the tree would have taken.
+    pub node_transitions: u64,
+    /// Symbols read with one lookup in the direct table used when no code exceeds 8 bits
+    pub direct_lookups: u64,
+    /// Payload bits read, not counting the header and the tree
+    pub bits_consumed: u64,
+}
+
+impl DecodeCounter for Stats {
+    fn transition(&mut self) {
+        self.node_transitions += 1;
+    }
+
+    fn direct_lookup(&mut self, length: u8) {
+        self.node_transitions += length as u64;
+        self.direct_lookups += 1;
+    }
+
+    fn bits(&mut self, count: usize) {
+        self.bits_consumed += count as u64;
+    }
+}
@@ -0,0 +1,233 @@
+use std::io::{self, Read, Write};
+
+use crate::{DecompressError, HuffmanArchive, HuffmanTree, Options};
+
+/// Compressing writer shaped like `flate2::write::*Encoder`: `new(inner)`, `Write`,
+/// `get_ref`/`get_mut` and `finish() -> io::Result<W>`. The tree depends on the whole input,
+/// so everything is buffered and the archive is written on `finish` (or, ignoring errors,
+/// when the encoder is dropped). Errors from the inner writer, e.g. `BrokenPipe`, are
+/// returned rather than panicking.
+pub struct PuhlEncoder<W: Write> {
+    inner: Option<W>,
+    options: Options,
+    buffer: Vec<u8>,
+    finished: bool,
+}
+
+impl<W: Write> PuhlEncoder<W> {
+    pub fn new(inner: W) -> Self {
+        Self::with_options(inner, Options::default())
+    }
+
+    pub fn with_options(inner: W, options: Options) -> Self {
+        Self {
+            inner: Some(inner),
+            options,
+            buffer: Vec::new(),
+            finished: false,
+        }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        self.inner.as_ref().unwrap()
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        self.inner.as_mut().unwrap()
+    }
+
+    /// Writes the archive and returns the inner writer
+    pub fn finish(mut self) -> io::Result<W> {
+        self.try_finish()?;
+        Ok(self.inner.take().unwrap())
+    }
+
+    // A failed write is not retried on drop, the inner writer may already hold part of the
+    // archive
+    fn try_finish(&mut self) -> io::Result<()> {
+        let Some(inner) = self.inner.as_mut().filter(|_| !self.finished) else {
+            return Ok(());
+        };
+        self.finished = true;
+
+        let tree = HuffmanTree::build(&self.buffer);
+        let archive = HuffmanArchive::compress_with_options(&self.buffer, &tree, &self.options);
+        inner.write_all(archive.as_raw_slice())?;
+        inner.flush()?;
+        self.buffer = Vec::new();
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for PuhlEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    // Nothing can be emitted before the whole input is known
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<W: Write> Drop for PuhlEncoder<W> {
+    fn drop(&mut self) {
+        let _ = self.try_finish();
+    }
+}
+
+// Bytes read from the inner reader at a time while a frame is incomplete. Each read takes at
+// least as much again as is buffered, so retrying the decode stays linear in the frame size.
+const READ_CHUNK: usize = 8 * 1024;
+
+/// Decompressing reader shaped like `flate2::read::*Decoder`: `new(inner)`, `Read`,
+/// `get_ref`/`get_mut` and `into_inner`. Archives written back to back are decoded one frame
+/// at a time, and only the frame being decoded is buffered. A frame's output is handed out
+/// once its checksum has been verified, so reads up to a corrupt frame succeed and then fail
+/// with `io::ErrorKind::InvalidData`, as does every later read.
+pub struct PuhlDecoder<R: Read> {
+    inner: R,
+    input: Vec<u8>,
+    at_eof: bool,
+    frame: Vec<u8>,
+    position: usize,
+    decoded_len: u64,
+    read_len: u64,
+    error: Option<DecompressError>,
+}
+
+impl<R: Read> PuhlDecoder<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            input: Vec::new(),
+            at_eof: false,
+            frame: Vec::new(),
+            position: 0,
+            decoded_len: 0,
+            read_len: 0,
+            error: None,
+        }
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Decodes and verifies the frames not read yet, discarding their output, and returns the
+    /// inner reader, so a caller that stops reading early still learns whether every checksum
+    /// matched. The `InvalidData` error holds the `DecompressError`.
+    pub fn finish(mut self) -> io::Result<R> {
+        while !self.fill()?.is_empty() {
+            self.position = self.frame.len();
+        }
+        Ok(self.inner)
+    }
+
+    /// Share of the decoded data read so far, from 0 before the first read to 1 once the end
+    /// of the input was reached. Only the frames decoded so far count, for an archive of
+    /// several frames it reaches 1 at the end of each frame.
+    pub fn progress(&self) -> f64 {
+        if self.at_eof && self.input.is_empty() && self.position == self.frame.len() {
+            1.0
+        } else if self.decoded_len == 0 {
+            0.0
+        } else {
+            self.read_len as f64 / self.decoded_len as f64
+        }
+    }
+
+    // The rest of the current frame, decoding the next one when it has all been read. Empty
+    // at the end of the input.
+    fn fill(&mut self) -> io::Result<&[u8]> {
+        if let Some(err) = &self.error {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, err.clone()));
+        }
+
+        while self.position == self.frame.len() {
+            if self.input.is_empty() && !self.read_more()? {
+                break;
+            }
+            match HuffmanArchive::decompress_first_frame(&self.input) {
+                Ok((frame, consumed)) => {
+                    self.input.drain(..consumed);
+                    self.decoded_len += frame.len() as u64;
+                    self.frame = frame;
+                    self.position = 0;
+                }
+                Err(err) if is_incomplete(&err) && self.read_more()? => {}
+                Err(err) => {
+                    self.error = Some(err.clone());
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, err));
+                }
+            }
+        }
+
+        Ok(&self.frame[self.position..])
+    }
+
+    // Reads at least as much again as is buffered, false once the inner reader has ended
+    fn read_more(&mut self) -> io::Result<bool> {
+        if self.at_eof {
+            return Ok(false);
+        }
+        let wanted = self.input.len().max(READ_CHUNK);
+        let read = (&mut self.inner)
+            .take(wanted as u64)
+            .read_to_end(&mut self.input)?;
+        self.at_eof = read < wanted;
+        Ok(read > 0)
+    }
+}
+
+// The errors `decompress_first_frame` gives for a frame that has not fully arrived yet
+fn is_incomplete(err: &DecompressError) -> bool {
+    matches!(
+        err,
+        DecompressError::TooShort
+            | DecompressError::TruncatedTree
+            | DecompressError::TruncatedPayload
+    )
+}
+
+impl<R: Read> Read for PuhlDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill()?;
+        let count = available.len().min(buf.len());
+        buf[..count].copy_from_slice(&available[..count]);
+        self.position += count;
+        self.read_len += count as u64;
+        Ok(count)
+    }
+}
+
+impl HuffmanArchive {
+    /// Decodes archives laid back to back like `decompress_frames`, writing each frame's
+    /// output to both writers as soon as it is decoded. Invalid archives surface as
+    /// `io::ErrorKind::InvalidData`, after the frames before them were written.
+    pub fn decompress_tee(
+        mut archive_bytes: &[u8],
+        mut first: impl Write,
+        mut second: impl Write,
+    ) -> io::Result<()> {
+        while !archive_bytes.is_empty() {
+            let (frame, consumed) = Self::decompress_frame(archive_bytes, None, &mut ())
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+            first.write_all(&frame)?;
+            second.write_all(&frame)?;
+            archive_bytes = &archive_bytes[consumed..];
+        }
+
+        first.flush()?;
+        second.flush()
+    }
+}
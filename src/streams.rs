@@ -0,0 +1,45 @@
+use bitvec::prelude::*;
+
+use crate::{ArchiveHeader, DecompressError, FLAG_BLOCKS, FLAG_STREAMS, HuffmanArchive, Options};
+
+impl HuffmanArchive {
+    /// Codes each stream with a tree of its own, like the literal and length trees of DEFLATE,
+    /// stored together as the blocks of one archive
+    pub fn compress_multistream(streams: &[&[u8]], options: &Options) -> BitVec<u8, Msb0> {
+        let mut ranges = Vec::with_capacity(streams.len());
+        let mut start = 0;
+        for stream in streams {
+            ranges.push(start..start + stream.len());
+            start += stream.len();
+        }
+        Self::compress_block_ranges(
+            &streams.concat(),
+            ranges,
+            FLAG_BLOCKS | FLAG_STREAMS,
+            options,
+        )
+    }
+
+    /// Every stream of an archive from `compress_multistream`, in order
+    pub fn decompress_multistream(archive_bytes: &[u8]) -> Result<Vec<Vec<u8>>, DecompressError> {
+        let header = ArchiveHeader::parse(archive_bytes)?;
+        if !header.has_streams() || !header.has_blocks() {
+            return Err(DecompressError::NotMultistream);
+        }
+
+        let data = Self::decompress(archive_bytes)?;
+        let mut rest = &data[..];
+        let count = header.entry_count.unwrap_or(0) as usize;
+        let mut streams = Vec::with_capacity(count);
+        for stream in 0..count {
+            let (_, len) = header.index_entry(archive_bytes, stream)?;
+            let (stream, tail) = rest
+                .split_at_checked(len)
+                .ok_or(DecompressError::InvalidBlockIndex)?;
+            streams.push(stream.to_vec());
+            rest = tail;
+        }
+
+        Ok(streams)
+    }
+}
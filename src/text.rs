@@ -0,0 +1,74 @@
+use bitvec::prelude::*;
+
+use crate::{DecompressError, FLAG_CRLF, HuffmanArchive, HuffmanTree, Options};
+
+impl HuffmanArchive {
+    /// Compresses text whose lines all end in CRLF as if they ended in LF alone, recording
+    /// that `decompress` has to put the CRs back. Other input, e.g. with a line ending in a
+    /// bare LF, is compressed as it is.
+    pub fn compress_text(data: &[u8], options: &Options) -> BitVec<u8, Msb0> {
+        let Some(normalized) = strip_crlf(data) else {
+            let tree = HuffmanTree::build(data);
+            return Self::compress_with_options(data, &tree, options);
+        };
+
+        let tree = HuffmanTree::build(&normalized);
+        let (tree_bits, tree_encoding, encoding_table) = Self::smallest_tree_encoding(&tree);
+
+        let mut header = Self::new_header(tree_bits.len() as u64, data, options);
+        tree_encoding.mark(&mut header);
+        header.flags |= FLAG_CRLF;
+        header.crlf_count = Some((data.len() - normalized.len()) as u64);
+
+        let mut archive = BitVec::<u8, Msb0>::new();
+
+        header.write(&mut archive);
+
+        Self::write_tree(&header, &tree_bits, &mut archive);
+
+        Self::encode_payload(&normalized, &encoding_table, &mut archive);
+
+        Self::seal_payload_checksum(&mut archive, 0);
+
+        archive
+    }
+}
+
+// `data` without the CR of each CRLF, if there is at least one and no LF stands alone
+fn strip_crlf(data: &[u8]) -> Option<Vec<u8>> {
+    let mut normalized = Vec::with_capacity(data.len());
+    for (i, &byte) in data.iter().enumerate() {
+        match byte {
+            b'\r' if data.get(i + 1) == Some(&b'\n') => {}
+            b'\n' if i == 0 || data[i - 1] != b'\r' => return None,
+            _ => normalized.push(byte),
+        }
+    }
+    (normalized.len() < data.len()).then_some(normalized)
+}
+
+/// Puts a CR back before every LF in the first `coded_len` bytes of `output`, spreading them
+/// over the whole of it
+pub(crate) fn restore_crlf(output: &mut [u8], coded_len: usize) -> Result<(), DecompressError> {
+    let line_ends = output[..coded_len]
+        .iter()
+        .filter(|&&byte| byte == b'\n')
+        .count();
+    if coded_len + line_ends != output.len() {
+        return Err(DecompressError::InvalidLineEndings);
+    }
+
+    // Back to front, so every byte is read before anything is written over it
+    let mut write = output.len();
+    for read in (0..coded_len).rev() {
+        let byte = output[read];
+        write -= 1;
+        output[write] = byte;
+        if byte == b'\n' {
+            write -= 1;
+            output[write] = b'\r';
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,59 @@
+use crate::HuffmanTree;
+
+/// How closely the byte frequencies of some data follow Zipf's law, from `analyze_zipf`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ZipfFit {
+    /// `s` in frequency ∝ rank^-s. Natural-language text is usually close to 1.
+    pub exponent: f64,
+    /// Root mean square of the residuals of the fit, in natural log of the frequency
+    pub rms_error: f64,
+    /// Distinct bytes the fit was made over
+    pub symbols: usize,
+}
+
+/// Least-squares fit of log frequency against log rank over the bytes that occur in `data`,
+/// most frequent first. With fewer than two distinct bytes there is nothing to fit, and the
+/// exponent and error are 0.
+pub fn analyze_zipf(data: &[u8]) -> ZipfFit {
+    let mut frequencies: Vec<usize> = HuffmanTree::frequencies(data)
+        .into_iter()
+        .filter(|&freq| freq != 0)
+        .collect();
+    frequencies.sort_unstable_by(|a, b| b.cmp(a));
+
+    let symbols = frequencies.len();
+    if symbols < 2 {
+        return ZipfFit {
+            exponent: 0.0,
+            rms_error: 0.0,
+            symbols,
+        };
+    }
+
+    let points: Vec<(f64, f64)> = frequencies
+        .iter()
+        .enumerate()
+        .map(|(rank, &freq)| (((rank + 1) as f64).ln(), (freq as f64).ln()))
+        .collect();
+    let n = symbols as f64;
+    let mean_x = points.iter().map(|&(x, _)| x).sum::<f64>() / n;
+    let mean_y = points.iter().map(|&(_, y)| y).sum::<f64>() / n;
+    let covariance: f64 = points
+        .iter()
+        .map(|&(x, y)| (x - mean_x) * (y - mean_y))
+        .sum();
+    let variance: f64 = points.iter().map(|&(x, _)| (x - mean_x).powi(2)).sum();
+
+    let slope = covariance / variance;
+    let intercept = mean_y - slope * mean_x;
+    let squared_error: f64 = points
+        .iter()
+        .map(|&(x, y)| (y - (intercept + slope * x)).powi(2))
+        .sum();
+
+    ZipfFit {
+        exponent: -slope,
+        rms_error: (squared_error / n).sqrt(),
+        symbols,
+    }
+}
@@ -0,0 +1,39 @@
+use compression::{CountingAllocator, HuffmanArchive, HuffmanTree};
+
+#[global_allocator]
+static ALLOC: CountingAllocator = CountingAllocator::new();
+
+// Every byte value about equally often, the largest tree there is
+fn full_alphabet(len: usize) -> Vec<u8> {
+    let mut state = 1u32;
+    (0..len)
+        .map(|_| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            (state >> 16) as u8
+        })
+        .collect()
+}
+
+// Counts for one input as (compress, decompress)
+fn allocations(data: &[u8]) -> (usize, usize) {
+    let (archive, compress) =
+        ALLOC.count(|| HuffmanArchive::compress(data, &HuffmanTree::build(data)).into_vec());
+    let (output, decompress) = ALLOC.count(|| HuffmanArchive::decompress(&archive).unwrap());
+    assert_eq!(output, data);
+    (compress, decompress)
+}
+
+#[test]
+fn allocations_are_bounded_by_the_alphabet() {
+    let (small_compress, small_decompress) = allocations(&full_alphabet(4 << 10));
+    let (large_compress, large_decompress) = allocations(&full_alphabet(256 << 10));
+
+    assert!(large_compress <= 2300, "compress took {large_compress}");
+    assert!(
+        large_decompress <= 850,
+        "decompress took {large_decompress}"
+    );
+    // 64 times the input only adds a few steps of geometric growth
+    assert!(large_compress.abs_diff(small_compress) <= 16);
+    assert!(large_decompress.abs_diff(small_decompress) <= 16);
+}
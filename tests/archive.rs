@@ -0,0 +1,343 @@
+use std::sync::atomic::AtomicBool;
+
+use compression::{ArchiveHeader, CompressError, DecompressError, HuffmanArchive, HuffmanTree};
+
+#[test]
+fn text_decodes_to_a_string() {
+    let text = "grüße, 世界\n";
+    let archive = HuffmanArchive::compress(text.as_bytes(), &HuffmanTree::build(text.as_bytes()));
+    assert_eq!(
+        HuffmanArchive::decompress_to_string(archive.as_raw_slice()).unwrap(),
+        text
+    );
+
+    let bytes = [0xff, 0xfe, b'a'];
+    let archive = HuffmanArchive::compress(&bytes, &HuffmanTree::build(&bytes));
+    assert!(matches!(
+        HuffmanArchive::decompress_to_string(archive.as_raw_slice()),
+        Err(DecompressError::InvalidUtf8(_))
+    ));
+}
+
+#[cfg(feature = "arena")]
+#[test]
+fn arena_decode_matches_the_vec_decode() {
+    let arena = bumpalo::Bump::new();
+    let inputs: [&[u8]; 3] = [include_bytes!("../src/archive.rs"), b"x", b""];
+    for data in inputs {
+        let archive = HuffmanArchive::compress(data, &HuffmanTree::build(data)).into_vec();
+        let decoded = HuffmanArchive::decompress_in(&archive, &arena).unwrap();
+        assert_eq!(decoded, HuffmanArchive::decompress(&archive).unwrap());
+    }
+}
+
+#[test]
+fn records_decode_by_index() {
+    let records: [&[u8]; 4] = [b"alpha", b"", b"gamma ray", b"delta"];
+    let archive = HuffmanArchive::compress_records(&records, &Default::default()).into_vec();
+
+    assert_eq!(HuffmanArchive::record_count(&archive), Ok(records.len()));
+    for index in [2, 0, 3, 1] {
+        assert_eq!(
+            HuffmanArchive::decompress_record(&archive, index).unwrap(),
+            records[index]
+        );
+    }
+    assert_eq!(
+        HuffmanArchive::decompress_record(&archive, 4),
+        Err(DecompressError::RecordOutOfRange { index: 4, count: 4 })
+    );
+    assert_eq!(
+        HuffmanArchive::decompress(&archive).unwrap(),
+        records.concat()
+    );
+
+    // Block archives have an index too, but of blocks
+    let blocks = HuffmanArchive::compress_blocks(&records.concat(), 4, &Default::default());
+    let blocks = blocks.into_vec();
+    assert_eq!(
+        HuffmanArchive::record_count(&blocks),
+        Err(DecompressError::NotRecords)
+    );
+    assert_eq!(
+        HuffmanArchive::decompress_record(&blocks, 0),
+        Err(DecompressError::NotRecords)
+    );
+}
+
+#[test]
+fn decoded_len_reads_the_header_only() {
+    for len in [0, 1, 1000] {
+        let data = vec![b'x'; len];
+        let archive = HuffmanArchive::compress(&data, &HuffmanTree::build(&data)).into_vec();
+        assert_eq!(HuffmanArchive::decoded_len(&archive), Ok(len as u64));
+        // The payload is not needed
+        let header_len = ArchiveHeader::parse(&archive).unwrap().byte_len();
+        assert_eq!(
+            HuffmanArchive::decoded_len(&archive[..header_len]),
+            Ok(len as u64)
+        );
+    }
+
+    let mut archive = HuffmanArchive::store(b"abc", &Default::default()).into_vec();
+    assert_eq!(HuffmanArchive::decoded_len(&archive), Ok(3));
+    archive[0] = b'P';
+    assert_eq!(
+        HuffmanArchive::decoded_len(&archive),
+        Err(DecompressError::InvalidMagic)
+    );
+}
+
+#[test]
+fn extracted_trees_have_the_compressing_codebook() {
+    let data = b"she sells sea shells by the sea shore";
+    let tree = HuffmanTree::build(data);
+    let archive = HuffmanArchive::compress(data, &tree).into_vec();
+    let extracted = HuffmanArchive::extract_tree(&archive).unwrap();
+    assert_eq!(extracted.get_encoding_table(), tree.get_encoding_table());
+
+    // Only the tree has to be there
+    let header = ArchiveHeader::parse(&archive).unwrap();
+    let tree_end = (header.body_offset() * 8 + header.tree_len as usize).div_ceil(8);
+    assert!(HuffmanArchive::extract_tree(&archive[..tree_end]).is_ok());
+
+    let stored = HuffmanArchive::store(data, &Default::default()).into_vec();
+    assert_eq!(
+        HuffmanArchive::extract_tree(&stored).err(),
+        Some(DecompressError::NoEmbeddedTree)
+    );
+}
+
+#[test]
+fn archives_sharing_a_tree_share_a_fingerprint() {
+    let tree = HuffmanTree::build(b"the quick brown fox jumps over the lazy dog, again and again");
+    let fingerprint = |data: &[u8]| {
+        HuffmanArchive::codebook_fingerprint(&HuffmanArchive::compress(data, &tree).into_vec())
+    };
+    assert_eq!(fingerprint(b"the dog"), Ok(tree.codebook_fingerprint()));
+    assert_eq!(fingerprint(b"quick brown foxes"), fingerprint(b"the dog"));
+
+    // Same code lengths, stored as lengths instead of the shape
+    let canonical = HuffmanTree::from_code_lengths(&tree.code_lengths()).unwrap();
+    assert_eq!(
+        HuffmanArchive::codebook_fingerprint(
+            &HuffmanArchive::compress(b"the dog", &canonical).into_vec()
+        ),
+        fingerprint(b"the dog")
+    );
+
+    let other = HuffmanTree::build(b"a different codebook");
+    assert_ne!(other.codebook_fingerprint(), tree.codebook_fingerprint());
+}
+
+#[test]
+fn cancelled_compression_gives_up() {
+    let data = b"cancel me ".repeat(1000);
+    let tree = HuffmanTree::build(&data);
+
+    let archive = HuffmanArchive::compress_cancellable(
+        &data,
+        &tree,
+        &Default::default(),
+        &AtomicBool::new(false),
+    )
+    .unwrap();
+    assert_eq!(archive, HuffmanArchive::compress(&data, &tree));
+
+    assert_eq!(
+        HuffmanArchive::compress_cancellable(
+            &data,
+            &tree,
+            &Default::default(),
+            &AtomicBool::new(true)
+        ),
+        Err(CompressError::Cancelled)
+    );
+}
+
+#[test]
+fn slice_compression_needs_room_for_the_archive() {
+    let data = b"fits in a stack buffer, fits in a stack buffer";
+    let expected = HuffmanArchive::compress(data, &HuffmanTree::build(data)).into_vec();
+
+    let mut out = [0xaa; 256];
+    let written = HuffmanArchive::compress_into_slice(data, &Default::default(), &mut out).unwrap();
+    assert_eq!(out[..written], expected);
+    assert!(out[written..].iter().all(|&byte| byte == 0xaa));
+
+    let mut exact = vec![0; expected.len()];
+    assert_eq!(
+        HuffmanArchive::compress_into_slice(data, &Default::default(), &mut exact),
+        Ok(expected.len())
+    );
+    assert_eq!(
+        HuffmanArchive::compress_into_slice(
+            data,
+            &Default::default(),
+            &mut out[..expected.len() - 1]
+        ),
+        Err(CompressError::BufferTooSmall {
+            needed: expected.len(),
+            available: expected.len() - 1
+        })
+    );
+}
+
+#[test]
+fn slice_decompression_matches_the_vec_decode() {
+    let data = b"decoded without a heap, decoded without a heap";
+    let archive = HuffmanArchive::compress(data, &HuffmanTree::build(data)).into_vec();
+
+    let mut out = [0; 128];
+    let written = HuffmanArchive::decompress_into_slice(&archive, &mut out).unwrap();
+    assert_eq!(
+        out[..written],
+        HuffmanArchive::decompress(&archive).unwrap()
+    );
+
+    let mut exact = [0; 46];
+    assert_eq!(
+        HuffmanArchive::decompress_into_slice(&archive, &mut exact),
+        Ok(data.len())
+    );
+    assert_eq!(
+        HuffmanArchive::decompress_into_slice(&archive, &mut out[..45]),
+        Err(DecompressError::BufferTooSmall {
+            needed: 46,
+            available: 45
+        })
+    );
+}
+
+#[test]
+fn overhead_dominates_tiny_archives_only() {
+    let options = Default::default();
+    let tiny = HuffmanArchive::header_overhead_fraction(b"hi", &options);
+    assert!(tiny > 0.95, "{tiny}");
+
+    let large = b"a large archive is mostly payload. ".repeat(3000);
+    let fraction = HuffmanArchive::header_overhead_fraction(&large, &options);
+    assert!(fraction < 0.01, "{fraction}");
+
+    // The header and tree of the archive `compress` writes
+    let archive = HuffmanArchive::compress(&large, &HuffmanTree::build(&large));
+    let header = ArchiveHeader::parse(archive.as_raw_slice()).unwrap();
+    let overhead = header.byte_len() * 8 + header.tree_len as usize;
+    assert_eq!(fraction, overhead as f64 / archive.len() as f64);
+}
+
+#[test]
+fn crlf_text_round_trips_exactly() {
+    let crlf = b"first line\r\nsecond line\r\n\r\nlast, unterminated";
+    let archive = HuffmanArchive::compress_text(crlf, &Default::default()).into_vec();
+    assert!(ArchiveHeader::parse(&archive).unwrap().has_crlf());
+    assert_eq!(HuffmanArchive::decompress(&archive).unwrap(), crlf);
+    assert_eq!(HuffmanArchive::decoded_len(&archive), Ok(crlf.len() as u64));
+
+    // A bare LF keeps the input as it is
+    let mixed = b"a windows line\r\na unix line\n";
+    let archive = HuffmanArchive::compress_text(mixed, &Default::default()).into_vec();
+    assert!(!ArchiveHeader::parse(&archive).unwrap().has_crlf());
+    assert_eq!(HuffmanArchive::decompress(&archive).unwrap(), mixed);
+}
+
+#[test]
+fn restricted_alphabets_reject_stray_bytes() {
+    const BASE64: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/=";
+    let encoded = b"aGVsbG8sIHJlc3RyaWN0ZWQgd29ybGQ=";
+
+    let archive = HuffmanArchive::compress_restricted(encoded, BASE64, &Default::default())
+        .unwrap()
+        .into_vec();
+    assert_eq!(HuffmanArchive::decompress(&archive).unwrap(), encoded);
+    // Seven bits are enough for every leaf
+    let plain = HuffmanArchive::compress(encoded, &HuffmanTree::build(encoded));
+    assert!(archive.len() < plain.as_raw_slice().len());
+
+    assert_eq!(
+        HuffmanArchive::compress_restricted(b"aGVs\nbG8=", BASE64, &Default::default()),
+        Err(CompressError::SymbolNotInTree {
+            byte: b'\n',
+            offset: 4
+        })
+    );
+}
+
+#[test]
+fn layout_points_at_the_tree_and_the_payload() {
+    let data = b"where does the payload start?";
+    let tree = HuffmanTree::build(data);
+    let archive = HuffmanArchive::compress(data, &tree);
+    let header = ArchiveHeader::parse(archive.as_raw_slice()).unwrap();
+
+    let layout = HuffmanArchive::layout(archive.as_raw_slice()).unwrap();
+    assert_eq!(layout.tree_start, header.byte_len() * 8);
+    assert_eq!(
+        layout.payload_start,
+        layout.tree_start + header.tree_len as usize
+    );
+
+    // The payload is the codes of the data, one after another
+    let codes = tree.get_encoding_table();
+    let payload: Vec<bool> = data
+        .iter()
+        .flat_map(|&byte| codes[byte as usize].iter().by_vals())
+        .collect();
+    let archive_payload: Vec<bool> = archive[layout.payload_start..].iter().by_vals().collect();
+    assert_eq!(archive_payload, payload);
+
+    let stored = HuffmanArchive::store(data, &Default::default()).into_vec();
+    let layout = HuffmanArchive::layout(&stored).unwrap();
+    assert_eq!(layout.payload_start, layout.tree_start);
+    assert_eq!(&stored[layout.payload_start / 8..], data);
+}
+
+#[test]
+fn double_coding_round_trips() {
+    let single = |data: &[u8]| HuffmanArchive::compress(data, &HuffmanTree::build(data)).len();
+    let options = Default::default();
+
+    // Near-random first pass output, the second tree only adds to it
+    let text = include_bytes!("../src/double.rs");
+    let double = HuffmanArchive::compress_double(text, &options);
+    assert!(double.len() > single(text));
+    assert_eq!(
+        HuffmanArchive::decompress(double.as_raw_slice()).unwrap(),
+        text
+    );
+
+    // Codes that add up to whole bytes repeat the same byte, which the second pass shrinks
+    let pattern = b"ab".repeat(4000);
+    let double = HuffmanArchive::compress_double(&pattern, &options);
+    assert!(double.len() * 4 < single(&pattern));
+    assert_eq!(
+        HuffmanArchive::decompress(double.as_raw_slice()).unwrap(),
+        pattern
+    );
+
+    let empty = HuffmanArchive::compress_double(b"", &options);
+    assert_eq!(
+        HuffmanArchive::decompress(empty.as_raw_slice()).unwrap(),
+        b""
+    );
+}
+
+#[test]
+fn payload_length_with_a_shared_tree_is_exact() {
+    let tree = HuffmanTree::build(b"a shared tree for many short messages");
+    for message in [&b""[..], b"a", b"tree", b"many short messages"] {
+        let bits = HuffmanArchive::payload_bit_len_with_tree(message, &tree).unwrap();
+        let archive =
+            HuffmanArchive::compress_with_tree(message, &tree, &Default::default()).unwrap();
+        let header = ArchiveHeader::parse(archive.as_raw_slice()).unwrap();
+        assert_eq!(archive.len() - header.byte_len() * 8, bits);
+    }
+
+    assert_eq!(
+        HuffmanArchive::payload_bit_len_with_tree(b"trees!", &tree),
+        Err(CompressError::SymbolNotInTree {
+            byte: b'!',
+            offset: 5
+        })
+    );
+}
@@ -0,0 +1,129 @@
+use compression::{ArchiveHeader, DecompressError, HuffmanArchive, Options};
+
+// Text whose byte distribution changes halfway through
+fn shifting_input() -> Vec<u8> {
+    let mut data = b"aaaabbbcc d".repeat(400);
+    data.extend(b"xyzzy 0123456789".repeat(300));
+    data
+}
+
+#[test]
+fn block_estimates_track_the_encoded_size() {
+    let data = shifting_input();
+    let boundaries: Vec<usize> = (1024..data.len()).step_by(1024).collect();
+
+    let estimates = HuffmanArchive::estimate_blocks(&data, &boundaries);
+    assert_eq!(estimates.len(), boundaries.len() + 1);
+
+    let estimated = estimates.iter().sum::<usize>();
+    let encoded = HuffmanArchive::compress_blocks(&data, 1024, &Default::default()).len();
+    // The archive adds its header and index, and pads every frame to a byte
+    assert!(estimated <= encoded, "{estimated} > {encoded}");
+    assert!(
+        encoded - estimated < encoded / 10,
+        "{estimated} vs {encoded}"
+    );
+}
+
+#[test]
+fn repeated_blocks_are_coded_once() {
+    let block = b"one block of text, repeated ".repeat(10);
+    let data = [&block[..], &block[..], b"and a different tail"].concat();
+    let boundaries = [block.len(), 2 * block.len()];
+
+    let plain = HuffmanArchive::compress_blocks_at(&data, &boundaries, &Default::default());
+    let options = Options {
+        dedup_blocks: true,
+        ..Default::default()
+    };
+    let deduped = HuffmanArchive::compress_blocks_at(&data, &boundaries, &options).into_vec();
+    // The second copy only costs its index entry
+    assert!(deduped.len() + block.len() / 2 < plain.len() / 8);
+
+    let header = ArchiveHeader::parse(&deduped).unwrap();
+    let index = &deduped[header.byte_len()..header.byte_len() + 32];
+    assert_eq!(index[..8], index[16..24], "both copies point at one frame");
+
+    assert_eq!(HuffmanArchive::decompress(&deduped).unwrap(), data);
+    assert_eq!(
+        HuffmanArchive::decompress_range(&deduped, block.len(), 2 * block.len()).unwrap(),
+        block
+    );
+}
+
+#[test]
+fn frames_are_shared_a_bounded_number_of_times() {
+    let data = vec![b'a'; 5000];
+    let options = Options {
+        dedup_blocks: true,
+        ..Default::default()
+    };
+    let archive = HuffmanArchive::compress_blocks(&data, 1, &options).into_vec();
+    assert_eq!(HuffmanArchive::decompress(&archive).unwrap(), data);
+
+    // 4096 blocks share the first frame, the rest a second copy
+    let header = ArchiveHeader::parse(&archive).unwrap();
+    let index = &archive[header.byte_len()..header.body_offset()];
+    let mut offsets: Vec<&[u8]> = index.chunks(16).map(|entry| &entry[..8]).collect();
+    offsets.dedup();
+    assert_eq!(offsets.len(), 2);
+}
+
+#[test]
+fn ranges_decode_across_blocks() {
+    let data = shifting_input();
+    let archive = HuffmanArchive::compress_blocks(&data, 1000, &Default::default()).into_vec();
+
+    for (start, end) in [(0, 0), (10, 20), (990, 1010), (500, 3500), (0, data.len())] {
+        assert_eq!(
+            HuffmanArchive::decompress_range(&archive, start, end).unwrap(),
+            data[start..end]
+        );
+    }
+    let len = data.len();
+    assert_eq!(
+        HuffmanArchive::decompress_range(&archive, 10, len + 1),
+        Err(DecompressError::RangeOutOfBounds {
+            start: 10,
+            end: len + 1,
+            len
+        })
+    );
+}
+
+#[test]
+fn thread_counts_do_not_change_the_archive() {
+    let data = shifting_input();
+    let with_threads = |threads| {
+        let options = Options {
+            threads,
+            ..Default::default()
+        };
+        HuffmanArchive::compress_blocks(&data, 500, &options)
+    };
+
+    let serial = with_threads(1);
+    for threads in [0, 2, 3, 64] {
+        assert_eq!(with_threads(threads), serial, "{threads} threads");
+    }
+    assert_eq!(
+        HuffmanArchive::decompress(serial.as_raw_slice()).unwrap(),
+        data
+    );
+}
+
+#[test]
+fn tuning_picks_the_smaller_block_size() {
+    let data = shifting_input();
+    let size_with =
+        |block_size| HuffmanArchive::compress_blocks(&data, block_size, &Default::default()).len();
+
+    // Blocks of 16 bytes spend more on trees than they save, one block for everything
+    // mixes both halves. The first half is 4400 bytes.
+    let candidates = [16, 4400, data.len()];
+    let tuned = HuffmanArchive::tune_block_size(&data, &candidates);
+    assert_eq!(tuned, 4400);
+    for other in candidates {
+        assert!(size_with(tuned) <= size_with(other), "{other}");
+    }
+}
@@ -0,0 +1,189 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+use compression::{
+    ArchiveHeader, FLAG_CODE_LENGTHS, FLAG_LEAF_WIDTH, HuffmanArchive, HuffmanTree,
+    canonical_codes, code_lengths_from_frequencies,
+};
+
+// xorshift64, so every run checks the same inputs
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, bound: u64) -> u64 {
+        self.next() % bound
+    }
+}
+
+// Inputs of varying length over alphabets of varying size, skewed towards the low symbols
+fn random_input(rng: &mut Rng) -> Vec<u8> {
+    let len = rng.below(1000) as usize;
+    let alphabet = 1 + rng.below(256);
+    let skew = rng.below(4);
+    (0..len)
+        .map(|_| {
+            let mut symbol = rng.below(alphabet);
+            for _ in 0..skew {
+                symbol = symbol.min(rng.below(alphabet));
+            }
+            symbol as u8
+        })
+        .collect()
+}
+
+#[test]
+fn shape_and_code_lengths_decode_alike() {
+    let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+    let random = (0..200).map(|_| random_input(&mut rng));
+    for data in [Vec::new(), vec![7; 10]].into_iter().chain(random) {
+        let tree = HuffmanTree::build(&data);
+
+        let shape = HuffmanArchive::compress_with_leaf_width(&data, &tree, 8, &Default::default())
+            .unwrap()
+            .into_vec();
+        let canonical = HuffmanArchive::compress_canonical(&data, &Default::default()).into_vec();
+        assert_ne!(
+            ArchiveHeader::parse(&shape).unwrap().flags & FLAG_LEAF_WIDTH,
+            0
+        );
+        assert_ne!(
+            ArchiveHeader::parse(&canonical).unwrap().flags & FLAG_CODE_LENGTHS,
+            0
+        );
+
+        assert_eq!(HuffmanArchive::decompress(&shape).unwrap(), data);
+        assert_eq!(HuffmanArchive::decompress(&canonical).unwrap(), data);
+        assert_eq!(
+            HuffmanArchive::extract_tree(&shape).unwrap().code_lengths(),
+            HuffmanArchive::extract_tree(&canonical)
+                .unwrap()
+                .code_lengths(),
+        );
+    }
+}
+
+fn code_strings(lengths: &[u8]) -> Vec<String> {
+    canonical_codes(lengths)
+        .iter()
+        .map(|code| {
+            code.iter()
+                .map(|bit| if *bit { '1' } else { '0' })
+                .collect()
+        })
+        .collect()
+}
+
+// The example of RFC 1951 section 3.2.2, symbols A-H
+#[test]
+fn rfc_1951_example_codes() {
+    let lengths = [3, 3, 3, 3, 3, 2, 4, 4];
+    assert_eq!(
+        code_strings(&lengths),
+        ["010", "011", "100", "101", "110", "00", "1110", "1111"]
+    );
+    assert_eq!(
+        code_lengths_from_frequencies(&[10, 10, 10, 10, 10, 20, 5, 5]),
+        lengths
+    );
+}
+
+// The fixed literal/length code of RFC 1951 section 3.2.6
+#[test]
+fn rfc_1951_fixed_codes() {
+    let mut lengths = vec![8; 144];
+    lengths.extend([9; 112]);
+    lengths.extend([7; 24]);
+    lengths.extend([8; 8]);
+    let codes = code_strings(&lengths);
+
+    let ranges = [
+        (0..144, 0b0011_0000, 8),
+        (144..256, 0b1_1001_0000, 9),
+        (256..280, 0b000_0000, 7),
+        (280..288, 0b1100_0000, 8),
+    ];
+    for (symbols, first, width) in ranges {
+        for (offset, symbol) in symbols.enumerate() {
+            let expected = format!("{:0width$b}", first + offset);
+            assert_eq!(codes[symbol], expected, "symbol {symbol}");
+        }
+    }
+}
+
+#[test]
+fn frequency_lengths_match_the_tree() {
+    let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+    for _ in 0..100 {
+        let data = random_input(&mut rng);
+        let frequency = HuffmanTree::frequencies(&data);
+        let tree_lengths = HuffmanTree::build(&data).code_lengths();
+        let lengths = code_lengths_from_frequencies(&frequency);
+
+        // Ties between equal weights can be merged in another order, giving other lengths for
+        // the same payload size
+        let bits = |lengths: &[u8]| -> usize {
+            frequency
+                .iter()
+                .zip(lengths)
+                .map(|(&freq, &length)| freq * length as usize)
+                .sum()
+        };
+        assert_eq!(bits(&lengths), bits(&tree_lengths));
+        assert!(HuffmanTree::from_code_lengths(&lengths.try_into().unwrap()).is_some());
+    }
+}
+
+#[test]
+fn frequency_lengths_match_the_tree_without_ties() {
+    let data: Vec<u8> = (0..8u8)
+        .flat_map(|byte| std::iter::repeat_n(byte, 3usize.pow(byte as u32)))
+        .collect();
+    let lengths = code_lengths_from_frequencies(&HuffmanTree::frequencies(&data));
+    assert_eq!(lengths, HuffmanTree::build(&data).code_lengths());
+}
+
+#[test]
+fn large_alphabet_lengths_are_optimal() {
+    let mut rng = Rng(0x853c_49e6_748f_ea9b);
+    for symbols in [2, 3, 300, 5000] {
+        let freqs: Vec<usize> = (0..symbols)
+            .map(|_| rng.below(4) as usize * rng.below(10_000) as usize)
+            .collect();
+        let lengths = code_lengths_from_frequencies(&freqs);
+
+        // Every merge of the two lightest nodes adds their weight to the payload once
+        let mut heap: BinaryHeap<Reverse<usize>> = freqs
+            .iter()
+            .filter(|&&f| f != 0)
+            .map(|&f| Reverse(f))
+            .collect();
+        let mut optimal = 0;
+        while heap.len() > 1 {
+            let merged = heap.pop().unwrap().0 + heap.pop().unwrap().0;
+            optimal += merged;
+            heap.push(Reverse(merged));
+        }
+        let bits: usize = freqs
+            .iter()
+            .zip(&lengths)
+            .map(|(&freq, &length)| freq * length as usize)
+            .sum();
+        assert_eq!(bits, optimal, "{symbols} symbols");
+
+        // Kraft equality, the code is complete
+        let max = *lengths.iter().max().unwrap() as u32;
+        let kraft: u128 = lengths
+            .iter()
+            .filter(|&&length| length != 0)
+            .map(|&length| 1u128 << (max - length as u32))
+            .sum();
+        assert_eq!(kraft, 1 << max, "{symbols} symbols");
+    }
+}
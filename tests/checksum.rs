@@ -0,0 +1,80 @@
+use compression::{
+    ArchiveHeader, DecompressError, FLAG_CHECKSUM, HeaderField, HuffmanArchive, HuffmanTree,
+    Options,
+};
+
+const DATA: &[u8] = b"checksums catch what the code alone would decode to garbage";
+
+fn compress(data: &[u8], options: &Options) -> Vec<u8> {
+    HuffmanArchive::compress_with_options(data, &HuffmanTree::build(data), options).into_vec()
+}
+
+#[test]
+fn archives_decode_with_and_without_a_checksum() {
+    let checked = compress(DATA, &Options::default());
+    let unchecked = compress(
+        DATA,
+        &Options {
+            checksum: false,
+            ..Options::default()
+        },
+    );
+
+    let header = ArchiveHeader::parse(&checked).unwrap();
+    assert!(header.has_checksum());
+    assert!(!ArchiveHeader::parse(&unchecked).unwrap().has_checksum());
+    assert_eq!(checked.len(), unchecked.len() + 4);
+
+    assert_eq!(HuffmanArchive::decompress(&checked).unwrap(), DATA);
+    assert_eq!(HuffmanArchive::decompress(&unchecked).unwrap(), DATA);
+
+    // The stored CRC is the 4 bytes after the fixed fields
+    let mut wrong = checked.clone();
+    wrong[ArchiveHeader::MIN_LEN] ^= 0xff;
+    assert!(matches!(
+        HuffmanArchive::decompress(&wrong),
+        Err(DecompressError::ChecksumMismatch { .. })
+    ));
+
+    // Clearing the flag drops the field, so the rest of the archive has to move up with it
+    let mut cleared = checked.clone();
+    cleared[3..5].copy_from_slice(&(header.flags & !FLAG_CHECKSUM).to_be_bytes());
+    cleared.drain(ArchiveHeader::MIN_LEN..ArchiveHeader::MIN_LEN + 4);
+    assert_eq!(cleared, unchecked);
+}
+
+#[test]
+fn payload_checksum_trips_before_the_data_checksum() {
+    let both = Options {
+        payload_checksum: true,
+        ..Options::default()
+    };
+    let archive = compress(DATA, &both);
+    let header = ArchiveHeader::parse(&archive).unwrap();
+    assert_eq!(HuffmanArchive::decompress(&archive).unwrap(), DATA);
+
+    // A flipped payload bit would decode to the wrong data as well, the payload CRC catches it
+    // without decoding
+    let mut corrupt = archive.clone();
+    *corrupt.last_mut().unwrap() ^= 0x01;
+    assert!(matches!(
+        HuffmanArchive::decompress(&corrupt),
+        Err(DecompressError::PayloadChecksumMismatch { .. })
+    ));
+
+    // Bytes that arrived intact but decode to something else only fail the data CRC
+    let (_, range) = header
+        .field_ranges()
+        .into_iter()
+        .find(|(field, _)| *field == HeaderField::Checksum)
+        .unwrap();
+    let mut wrong = archive.clone();
+    wrong[range].copy_from_slice(&0u32.to_be_bytes());
+    assert_eq!(
+        HuffmanArchive::decompress(&wrong),
+        Err(DecompressError::ChecksumMismatch {
+            expected: 0,
+            actual: header.checksum.unwrap(),
+        })
+    );
+}
@@ -0,0 +1,300 @@
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_compression"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+// Runs with `input` on stdin
+fn run_with_input(args: &[&str], input: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_compression"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // A run that fails on its arguments may exit before reading any input
+    if let Err(err) = child.stdin.take().unwrap().write_all(input) {
+        assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+    }
+    child.wait_with_output().unwrap()
+}
+
+// A fresh directory for one test, under the target directory cargo gives integration tests
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn splitting_again_removes_stale_volumes() {
+    let dir = scratch_dir("split");
+    let input = dir.join("input.txt");
+    let data: Vec<u8> = (0..20_000u32)
+        .flat_map(|i| (i % 251).to_be_bytes())
+        .collect();
+    fs::write(&input, &data).unwrap();
+    let input = input.to_str().unwrap();
+
+    assert!(run(&["--split", "1K", input]).status.success());
+    assert!(dir.join("input.txt.jp.010").exists());
+
+    assert!(run(&["--split", "1M", input]).status.success());
+    assert!(!dir.join("input.txt.jp.002").exists());
+
+    let restored = run(&["-d", &format!("{input}.jp.001")]);
+    assert!(restored.status.success());
+    assert_eq!(restored.stdout, data);
+}
+
+#[test]
+fn decompression_errors_are_reported_once() {
+    let output = run_with_input(&["-d"], b"JP but not an archive");
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(stderr.matches("Error:").count(), 1, "{stderr}");
+}
+
+#[test]
+fn only_split_archives_are_read_as_volumes() {
+    let dir = scratch_dir("not-volumes");
+    let first = dir.join("take.001");
+    fs::write(&first, b"the first take").unwrap();
+    fs::write(dir.join("take.002"), b"the second take").unwrap();
+
+    // Compressing reads the named file alone
+    let archive = run(&[first.to_str().unwrap()]);
+    assert!(archive.status.success());
+    assert_eq!(
+        run_with_input(&["-d"], &archive.stdout).stdout,
+        b"the first take"
+    );
+
+    // So does decompressing a ".001" that is not a ".jp" volume
+    let packed = dir.join("packed.001");
+    fs::write(&packed, &archive.stdout).unwrap();
+    fs::write(dir.join("packed.002"), b"not an archive").unwrap();
+    let restored = run(&["-d", packed.to_str().unwrap()]);
+    assert!(restored.status.success());
+    assert_eq!(restored.stdout, b"the first take");
+}
+
+#[test]
+fn bad_split_sizes_are_rejected() {
+    for size in ["0", "17179869184G"] {
+        let output = run(&["--split", size, "unused"]);
+        assert_eq!(output.status.code(), Some(2), "--split {size}");
+    }
+}
+
+#[test]
+fn best_does_not_suggest_store() {
+    // Every byte value once, which huffman coding can only grow
+    let input: Vec<u8> = (0..=255).collect();
+
+    let plain = run_with_input(&[], &input);
+    assert!(String::from_utf8_lossy(&plain.stderr).contains("consider --store"));
+
+    for mode in [&["-9"][..], &["--text"], &["--double"], &["--blocks", "64"]] {
+        let output = run_with_input(mode, &input);
+        assert!(output.status.success());
+        assert!(
+            output.stderr.is_empty(),
+            "{mode:?}: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+}
+
+#[test]
+fn directories_are_only_packed() {
+    let dir = scratch_dir("directory");
+    fs::create_dir_all(dir.join("tree/sub")).unwrap();
+    fs::write(dir.join("tree/a.txt"), "first").unwrap();
+    fs::write(dir.join("tree/sub/b.txt"), "second").unwrap();
+    let tree = dir.join("tree");
+    let tree = tree.to_str().unwrap();
+
+    for mode in ["-d", "--info", "--verify", "--store", "--text", "-9"] {
+        let output = run(&[mode, tree]);
+        assert_eq!(output.status.code(), Some(2), "{mode}");
+    }
+
+    let packed = run(&[tree]);
+    assert!(packed.status.success());
+    let out = dir.join("out");
+    fs::create_dir_all(&out).unwrap();
+    let unpacked = run_with_input(&["-d", "-C", out.to_str().unwrap()], &packed.stdout);
+    assert!(unpacked.status.success());
+    assert_eq!(fs::read(out.join("sub/b.txt")).unwrap(), b"second");
+}
+
+#[test]
+fn store_warning_follows_the_input() {
+    let incompressible: Vec<u8> = (0..=255).collect();
+    let compressible = b"so much repetition, so much repetition, so much repetition".repeat(20);
+
+    let warned = run_with_input(&[], &incompressible);
+    assert!(String::from_utf8_lossy(&warned.stderr).contains("consider --store"));
+    assert!(run_with_input(&["-q"], &incompressible).stderr.is_empty());
+
+    let quiet = run_with_input(&[], &compressible);
+    assert!(quiet.status.success());
+    assert!(!String::from_utf8_lossy(&quiet.stderr).contains("Warning"));
+}
+
+#[test]
+fn verify_reports_through_the_exit_code() {
+    let archive = run_with_input(&[], b"verify me, verify me, verify me").stdout;
+
+    let ok = run_with_input(&["--verify"], &archive);
+    assert!(ok.status.success());
+    assert_eq!(ok.stdout, b"OK\n");
+
+    let mut corrupt = archive.clone();
+    *corrupt.last_mut().unwrap() ^= 0xff;
+    let failed = run_with_input(&["--verify"], &corrupt);
+    assert_eq!(failed.status.code(), Some(1));
+    assert!(failed.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&failed.stderr).starts_with("Error: "));
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn gzip_wrapped_archives_decode() {
+    use flate2::{Compression, write::GzEncoder};
+
+    let data = b"gzipped in transit, gzipped in transit";
+    let archive = run_with_input(&[], data).stdout;
+    let mut gzip = GzEncoder::new(Vec::new(), Compression::default());
+    gzip.write_all(&archive).unwrap();
+    let wrapped = gzip.finish().unwrap();
+
+    assert_eq!(run_with_input(&["-d"], &wrapped).stdout, data);
+    assert_eq!(run_with_input(&["-d"], &archive).stdout, data);
+}
+
+#[test]
+fn comments_show_in_info() {
+    let data = b"tagged with where it came from";
+    let tagged = run_with_input(&["--comment", "build 123 · nightly"], data).stdout;
+    let info = String::from_utf8(run_with_input(&["--info"], &tagged).stdout).unwrap();
+    assert!(
+        info.contains("\ncomment:     build 123 · nightly\n"),
+        "{info}"
+    );
+    assert_eq!(run_with_input(&["-d"], &tagged).stdout, data);
+
+    let untagged = run_with_input(&[], data).stdout;
+    let info = String::from_utf8(run_with_input(&["--info"], &untagged).stdout).unwrap();
+    assert!(!info.contains("comment:"), "{info}");
+    assert_eq!(
+        tagged.len(),
+        untagged.len() + 2 + "build 123 · nightly".len()
+    );
+}
+
+#[test]
+fn thread_counts_give_the_same_blocks() {
+    let data = b"threads only change how fast blocks are coded, ".repeat(100);
+    let serial = run_with_input(&["--blocks", "256", "--threads", "1"], &data);
+    assert!(serial.status.success());
+    for threads in ["0", "4"] {
+        let parallel = run_with_input(&["--blocks", "256", "--threads", threads], &data);
+        assert_eq!(parallel.stdout, serial.stdout, "{threads} threads");
+    }
+    assert_eq!(run_with_input(&["-d"], &serial.stdout).stdout, data);
+
+    assert_eq!(
+        run_with_input(&["--threads", "many"], &data).status.code(),
+        Some(2)
+    );
+}
+
+#[test]
+fn batches_match_single_runs() {
+    let dir = scratch_dir("cli-batch");
+    let inputs: [(&str, &[u8]); 3] = [
+        ("a.txt", b"the first of three files, the first of three"),
+        ("b.bin", &[0, 1, 2, 3, 255, 254, 0, 0, 0, 7]),
+        ("empty", b""),
+    ];
+    let paths: Vec<String> = inputs
+        .iter()
+        .map(|(name, contents)| {
+            let path = dir.join(name);
+            fs::write(&path, contents).unwrap();
+            path.to_str().unwrap().to_owned()
+        })
+        .collect();
+
+    let batch = run(&paths.iter().map(String::as_str).collect::<Vec<_>>());
+    assert!(batch.status.success());
+    assert!(batch.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&batch.stderr).starts_with("3 files, "));
+
+    for path in &paths {
+        let single = run(&[path]);
+        assert!(single.status.success());
+        assert_eq!(
+            fs::read(format!("{path}.jp")).unwrap(),
+            single.stdout,
+            "{path}"
+        );
+    }
+}
+
+#[test]
+fn chunks_flow_before_the_input_ends() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_compression"))
+        .args(["--chunk", "64"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    let mut stdout = child.stdout.take().unwrap();
+
+    // The first line comes out as a frame while stdin is still open
+    stdin.write_all(b"first line\n").unwrap();
+    stdin.flush().unwrap();
+    let (sender, receiver) = mpsc::channel();
+    let reader = thread::spawn(move || {
+        // A read can return part of the frame, keep going until it decodes
+        let mut frame = Vec::new();
+        let mut buf = [0; 1024];
+        while frame.is_empty() || compression::HuffmanArchive::decompress_frames(&frame).is_err() {
+            let read = stdout.read(&mut buf).unwrap();
+            assert!(read > 0, "output ended inside the first frame");
+            frame.extend_from_slice(&buf[..read]);
+        }
+        sender.send(frame).unwrap();
+        stdout
+    });
+    let frame = receiver.recv_timeout(Duration::from_secs(10)).unwrap();
+    let mut stdout = reader.join().unwrap();
+    assert_eq!(
+        compression::HuffmanArchive::decompress_frames(&frame).unwrap(),
+        b"first line\n"
+    );
+
+    stdin.write_all(b"and the rest of the stream\n").unwrap();
+    drop(stdin);
+    let mut archive = frame;
+    stdout.read_to_end(&mut archive).unwrap();
+    assert!(child.wait().unwrap().success());
+
+    let decoded = run_with_input(&["-d", "--chunk", "16"], &archive);
+    assert_eq!(decoded.stdout, b"first line\nand the rest of the stream\n");
+}
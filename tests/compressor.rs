@@ -0,0 +1,52 @@
+use compression::{Compressor, HuffmanArchive, HuffmanTree, Options};
+
+const MESSAGES: [&[u8]; 3] = [
+    b"GET /index.html HTTP/1.1",
+    b"GET /style.css HTTP/1.1",
+    b"POST /login HTTP/1.1",
+];
+
+#[test]
+fn presized_buffers_do_not_grow() {
+    let mut compressor = Compressor::with_capacity(1024);
+    let capacity = compressor.capacity();
+    assert!(capacity >= 1024);
+
+    for message in MESSAGES {
+        let expected = HuffmanArchive::compress(message, &HuffmanTree::build(message)).into_vec();
+        assert_eq!(compressor.compress(message), expected);
+        assert_eq!(compressor.capacity(), capacity);
+    }
+}
+
+#[test]
+fn buffers_grow_to_the_largest_archive() {
+    let mut compressor = Compressor::new();
+    let large = MESSAGES.concat().repeat(20);
+    let large_len = compressor.compress(&large).len();
+    let capacity = compressor.capacity();
+    assert!(capacity >= large_len);
+
+    let small = compressor.compress(MESSAGES[0]).to_vec();
+    assert_eq!(HuffmanArchive::decompress(&small).unwrap(), MESSAGES[0]);
+    assert_eq!(compressor.capacity(), capacity);
+}
+
+#[test]
+fn presized_buffers_keep_their_options() {
+    let options = Options {
+        checksum: false,
+        comment: Some("presized".into()),
+        ..Options::default()
+    };
+    let mut compressor = Compressor::with_options_and_capacity(options.clone(), 1024);
+    let capacity = compressor.capacity();
+    assert!(capacity >= 1024);
+
+    for message in MESSAGES {
+        let tree = HuffmanTree::build(message);
+        let expected = HuffmanArchive::compress_with_options(message, &tree, &options).into_vec();
+        assert_eq!(compressor.compress(message), expected);
+        assert_eq!(compressor.capacity(), capacity);
+    }
+}
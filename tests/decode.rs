@@ -0,0 +1,76 @@
+use bitvec::prelude::*;
+use compression::{ArchiveBuilder, HuffmanArchive, HuffmanTree};
+
+// Tree shape bits, 0 for an internal node and 1 plus the byte for a leaf
+fn shape(nodes: &[Option<u8>]) -> HuffmanTree {
+    let mut bits = BitVec::<u8, Msb0>::new();
+    for node in nodes {
+        match node {
+            Some(byte) => {
+                bits.push(true);
+                bits.extend_from_bitslice(byte.view_bits::<Msb0>());
+            }
+            None => bits.push(false),
+        }
+    }
+    HuffmanTree::deserialize_shape(&mut bits.into_iter()).unwrap()
+}
+
+fn decode(tree: &HuffmanTree, payload: BitVec<u8, Msb0>, len: u64) -> Vec<u8> {
+    let archive = ArchiveBuilder::new()
+        .tree(tree)
+        .data_len(len)
+        .payload_bits(payload)
+        .build();
+    HuffmanArchive::decompress(&archive).unwrap()
+}
+
+#[test]
+fn single_symbol_path_matches_the_general_one() {
+    // A root with two leaves takes the one bit per symbol path, the same codes one level
+    // deeper on the right go through the table
+    let two_leaves = shape(&[None, Some(b'z'), Some(0)]);
+    let deeper = shape(&[None, Some(b'z'), None, Some(0), Some(0)]);
+
+    let payload = bitvec![u8, Msb0; 0; 37];
+    assert_eq!(decode(&two_leaves, payload.clone(), 37), vec![b'z'; 37]);
+    assert_eq!(decode(&deeper, payload, 37), vec![b'z'; 37]);
+
+    let data = vec![b'q'; 1000];
+    let archive = HuffmanArchive::compress(&data, &HuffmanTree::build(&data)).into_vec();
+    assert_eq!(HuffmanArchive::decompress(&archive).unwrap(), data);
+}
+
+// A chain of `depth` internal nodes, the leaf for `b'a' + k` at depth k + 1 off each one and
+// two leaves at the bottom
+fn chain(depth: u8) -> HuffmanTree {
+    let mut nodes = Vec::new();
+    for k in 0..depth {
+        nodes.extend([None, Some(b'a' + k)]);
+    }
+    nodes.pop();
+    nodes.extend([Some(b'0'), Some(b'1')]);
+    shape(&nodes)
+}
+
+#[test]
+fn direct_table_matches_the_tree_walk() {
+    // Codes of up to 8 bits decode through the direct table, one more level walks the tree.
+    // The first seven bytes have the same code in both, k ones then a zero.
+    let direct = chain(8);
+    let walked = chain(9);
+    assert_eq!(direct.code_length_extremes(), (1, 8));
+    assert_eq!(walked.code_length_extremes(), (1, 9));
+
+    let mut payload = BitVec::<u8, Msb0>::new();
+    let mut expected = Vec::new();
+    for i in 0..500u32 {
+        let k = (i * 7 + i / 3) % 7;
+        payload.extend(std::iter::repeat_n(true, k as usize));
+        payload.push(false);
+        expected.push(b'a' + k as u8);
+    }
+    let len = expected.len() as u64;
+    assert_eq!(decode(&direct, payload.clone(), len), expected);
+    assert_eq!(decode(&walked, payload, len), expected);
+}
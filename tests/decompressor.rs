@@ -0,0 +1,71 @@
+use compression::{DecompressError, Decompressor, HuffmanArchive, HuffmanTree, Options};
+
+const MESSAGES: [&[u8]; 3] = [
+    b"GET /index.html HTTP/1.1",
+    b"GET /style.css HTTP/1.1",
+    b"POST /login HTTP/1.1",
+];
+
+fn preset_archive(data: &[u8], tree: &HuffmanTree) -> Vec<u8> {
+    HuffmanArchive::compress_with_tree(data, tree, &Options::default())
+        .unwrap()
+        .into_vec()
+}
+
+#[test]
+fn cached_table_decodes_like_a_fresh_one() {
+    let tree = HuffmanTree::build(&MESSAGES.concat());
+    let mut cached = Decompressor::with_preset_tree(HuffmanTree::build(&MESSAGES.concat()));
+
+    for message in MESSAGES.iter().cycle().take(9) {
+        let archive = preset_archive(message, &tree);
+        let fresh = Decompressor::with_preset_tree(HuffmanTree::build(&MESSAGES.concat()))
+            .decompress(&archive)
+            .unwrap();
+        assert_eq!(cached.decompress(&archive).unwrap(), fresh);
+        assert_eq!(fresh, *message);
+    }
+}
+
+#[test]
+fn preset_archives_need_the_tree() {
+    let tree = HuffmanTree::build(MESSAGES[0]);
+    let archive = preset_archive(MESSAGES[0], &tree);
+    assert_eq!(
+        HuffmanArchive::decompress(&archive),
+        Err(DecompressError::MissingPresetTree)
+    );
+    assert_eq!(
+        Decompressor::new().decompress(&archive),
+        Err(DecompressError::MissingPresetTree)
+    );
+
+    // Replacing the tree drops the table cached for the old one
+    let mut decompressor = Decompressor::with_preset_tree(HuffmanTree::build(b"xyz"));
+    let other = preset_archive(b"zyx", &HuffmanTree::build(b"xyz"));
+    assert_eq!(decompressor.decompress(&other).unwrap(), b"zyx");
+    decompressor.set_preset_tree(HuffmanTree::build(MESSAGES[0]));
+    assert_eq!(decompressor.decompress(&archive).unwrap(), MESSAGES[0]);
+}
+
+#[test]
+fn presized_output_buffers_do_not_grow() {
+    let mut decompressor = Decompressor::with_capacity(1024);
+    let capacity = decompressor.capacity();
+    assert!(capacity >= 1024);
+
+    for message in MESSAGES {
+        let archive = HuffmanArchive::compress(message, &HuffmanTree::build(message)).into_vec();
+        assert_eq!(decompressor.decompress_buffered(&archive).unwrap(), message);
+        assert_eq!(decompressor.capacity(), capacity);
+    }
+
+    let tree = HuffmanTree::build(&MESSAGES.concat());
+    decompressor.set_preset_tree(HuffmanTree::build(&MESSAGES.concat()));
+    let archive = preset_archive(MESSAGES[1], &tree);
+    assert_eq!(
+        decompressor.decompress_buffered(&archive).unwrap(),
+        MESSAGES[1]
+    );
+    assert_eq!(decompressor.capacity(), capacity);
+}
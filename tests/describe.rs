@@ -0,0 +1,62 @@
+use bitvec::prelude::*;
+use compression::{
+    ArchiveDescription, BlockType, ChecksumKind, HuffmanArchive, HuffmanTree, Options, PrePass,
+};
+
+const TEXT: &[u8] = b"line one\r\nline two\r\nline three\r\n";
+
+fn describe(archive: BitVec<u8, Msb0>) -> ArchiveDescription {
+    HuffmanArchive::describe(archive.as_raw_slice()).unwrap()
+}
+
+#[test]
+fn describe_reports_how_archives_were_made() {
+    let plain = describe(HuffmanArchive::compress(TEXT, &HuffmanTree::build(TEXT)));
+    assert_eq!(plain.version, 2);
+    assert_eq!(plain.block_type, BlockType::Single);
+    assert_eq!(plain.checksum, ChecksumKind::Crc32);
+    assert_eq!(plain.leaf_width, 8);
+    assert!(!plain.preset_tree && !plain.pre_passes_applied && !plain.crlf_line_endings);
+    assert_eq!(plain.comment, None);
+
+    let options = Options {
+        checksum: false,
+        comment: Some("nightly backup".into()),
+        payload_checksum: true,
+        ..Options::default()
+    };
+    let stored = describe(HuffmanArchive::store(TEXT, &options));
+    assert_eq!(stored.block_type, BlockType::Stored);
+    assert_eq!(stored.checksum, ChecksumKind::None);
+    assert_eq!(stored.comment.as_deref(), Some("nightly backup"));
+    assert!(stored.payload_checksum);
+
+    let text = describe(HuffmanArchive::compress_text(TEXT, &Options::default()));
+    assert!(text.crlf_line_endings);
+
+    let passes = [PrePass::Delta, PrePass::MoveToFront];
+    let pre_passed = describe(HuffmanArchive::compress_with_pre_passes(
+        TEXT,
+        &passes,
+        &Options::default(),
+    ));
+    assert!(pre_passed.pre_passes_applied);
+    assert_eq!(pre_passed.pre_passes, passes);
+
+    let double = describe(HuffmanArchive::compress_double(TEXT, &Options::default()));
+    assert!(double.double_huffman);
+
+    let tree = HuffmanTree::build(TEXT);
+    let preset =
+        describe(HuffmanArchive::compress_with_tree(TEXT, &tree, &Options::default()).unwrap());
+    assert!(preset.preset_tree);
+
+    let records = HuffmanArchive::compress_records(&[b"a", b"b"], &Options::default());
+    assert_eq!(describe(records).block_type, BlockType::Records);
+    let files = HuffmanArchive::compress_files(&[("a.txt", b"a")], &Options::default());
+    assert_eq!(describe(files).block_type, BlockType::Files);
+    let blocks = HuffmanArchive::compress_blocks(TEXT, 8, &Options::default());
+    assert_eq!(describe(blocks).block_type, BlockType::Blocks);
+    let streams = HuffmanArchive::compress_multistream(&[b"ab", b"cd"], &Options::default());
+    assert_eq!(describe(streams).block_type, BlockType::Streams);
+}
@@ -0,0 +1,73 @@
+use compression::{DecompressError, Decompressor, DictMode, HuffmanArchive, HuffmanTree};
+
+const SAMPLE: &[u8] = b"the quick brown fox jumps over the lazy dog, again and again";
+
+// A dictionary whose shape is not its canonical code, so decoding with the shape turns to noise
+fn dictionary() -> HuffmanTree {
+    let tree = HuffmanTree::build(SAMPLE);
+    let canonical = HuffmanTree::from_code_lengths(&tree.code_lengths()).unwrap();
+    assert_ne!(tree.get_encoding_table(), canonical.get_encoding_table());
+    tree
+}
+
+fn reference_archive(data: &[u8], dictionary: &HuffmanTree) -> Vec<u8> {
+    HuffmanArchive::compress_with_dictionary(
+        data,
+        dictionary,
+        DictMode::Reference,
+        &Default::default(),
+    )
+    .unwrap()
+    .into_vec()
+}
+
+#[test]
+fn decompressor_decodes_reference_archives() {
+    let dictionary = dictionary();
+    let data = b"a lazy fox and a quick dog";
+    let archive = reference_archive(data, &dictionary);
+
+    let mut decompressor = Decompressor::with_preset_tree(HuffmanTree::build(SAMPLE));
+    assert_eq!(decompressor.decompress(&archive).unwrap(), data);
+    // Again from the cached table
+    assert_eq!(decompressor.decompress(&archive).unwrap(), data);
+    assert_eq!(
+        HuffmanArchive::decompress_with_dictionary(&archive, &dictionary).unwrap(),
+        data
+    );
+}
+
+#[test]
+fn decompressor_rejects_the_wrong_dictionary() {
+    let archive = reference_archive(b"dog", &dictionary());
+    let other = HuffmanTree::build(b"an entirely different dictionary, dog");
+
+    let mut decompressor = Decompressor::with_preset_tree(other);
+    assert!(matches!(
+        decompressor.decompress(&archive),
+        Err(DecompressError::DictionaryMismatch { .. })
+    ));
+}
+
+#[test]
+fn reference_archives_have_a_fingerprint() {
+    let dictionary = dictionary();
+    let archive = reference_archive(b"dog", &dictionary);
+    assert_eq!(
+        HuffmanArchive::codebook_fingerprint(&archive),
+        Ok(dictionary.codebook_fingerprint())
+    );
+
+    let embedded = HuffmanArchive::compress_with_dictionary(
+        b"dog",
+        &dictionary,
+        DictMode::Embed,
+        &Default::default(),
+    )
+    .unwrap()
+    .into_vec();
+    assert_eq!(
+        HuffmanArchive::codebook_fingerprint(&embedded),
+        HuffmanArchive::codebook_fingerprint(&archive)
+    );
+}
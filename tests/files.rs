@@ -0,0 +1,92 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use compression::{DecompressError, HuffmanArchive};
+
+// A fresh directory for one test, under the target directory cargo gives integration tests
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn packed(files: &[(&str, &[u8])]) -> Vec<u8> {
+    HuffmanArchive::compress_files(files, &Default::default()).into_vec()
+}
+
+// The `UnsafePath` an extraction failed with
+fn unsafe_path(err: io::Error) -> String {
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    match err.into_inner().unwrap().downcast::<DecompressError>() {
+        Ok(err) => match *err {
+            DecompressError::UnsafePath(path) => path,
+            err => panic!("unexpected error {err}"),
+        },
+        Err(err) => panic!("unexpected error {err}"),
+    }
+}
+
+#[test]
+fn files_extract_under_the_target() {
+    let target = scratch_dir("files-extract");
+    let archive = packed(&[("notes.txt", b"first"), ("./deep/er/data.bin", b"\0\x01")]);
+    HuffmanArchive::extract_files(&archive, &target).unwrap();
+    assert_eq!(fs::read(target.join("notes.txt")).unwrap(), b"first");
+    assert_eq!(
+        fs::read(target.join("deep/er/data.bin")).unwrap(),
+        b"\0\x01"
+    );
+}
+
+#[test]
+fn paths_leaving_the_target_are_refused() {
+    let target = scratch_dir("files-unsafe/inside");
+    for path in [
+        "../../etc/passwd",
+        "/etc/passwd",
+        "sub/../../escape",
+        "",
+        ".",
+    ] {
+        let archive = packed(&[("harmless.txt", b"ok"), (path, b"root::0:0")]);
+        let err = HuffmanArchive::extract_files(&archive, &target).unwrap_err();
+        assert_eq!(unsafe_path(err), path);
+        // Checked before anything is written
+        assert!(!target.join("harmless.txt").exists(), "{path}");
+    }
+    assert!(!target.parent().unwrap().join("escape").exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn symlinks_in_the_target_are_not_followed() {
+    let target = scratch_dir("files-symlink");
+    let outside = scratch_dir("files-symlink-outside");
+    std::os::unix::fs::symlink(&outside, target.join("link")).unwrap();
+
+    let archive = packed(&[("link/planted", b"gotcha")]);
+    let err = HuffmanArchive::extract_files(&archive, &target).unwrap_err();
+    assert_eq!(unsafe_path(err), "link/planted");
+    assert!(!outside.join("planted").exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn late_symlinked_paths_stop_extraction_before_any_write() {
+    let target = scratch_dir("files-late-symlink");
+    let outside = scratch_dir("files-late-symlink-outside");
+    std::os::unix::fs::symlink(&outside, target.join("link")).unwrap();
+
+    let archive = packed(&[
+        ("first.txt", b"fine"),
+        ("sub/second.txt", b"fine too"),
+        ("link/planted", b"gotcha"),
+    ]);
+    let err = HuffmanArchive::extract_files(&archive, &target).unwrap_err();
+    assert_eq!(unsafe_path(err), "link/planted");
+    assert!(!target.join("first.txt").exists());
+    assert!(!target.join("sub").exists());
+    assert!(!outside.join("planted").exists());
+}
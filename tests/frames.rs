@@ -0,0 +1,124 @@
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+use compression::{ArchiveLog, FrameEncoder, HuffmanArchive, encode_frame};
+
+fn scratch_file(name: &str) -> PathBuf {
+    let path = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name);
+    let _ = fs::remove_file(&path);
+    path
+}
+
+#[test]
+fn log_appends_decode_as_one() {
+    let path = scratch_file("frames.log");
+    let entries: [&[u8]; 3] = [b"started\n", b"", b"request served in 4 ms\n"];
+
+    // Reopened for every entry, as a logger appending over time would
+    for entry in entries {
+        ArchiveLog::open(&path).unwrap().append(entry).unwrap();
+    }
+
+    let log = fs::read(&path).unwrap();
+    assert_eq!(
+        HuffmanArchive::decompress_frames(&log).unwrap(),
+        entries.concat()
+    );
+    HuffmanArchive::validate(&log).unwrap();
+
+    let (first, consumed) = HuffmanArchive::decompress_first_frame(&log).unwrap();
+    assert_eq!(first, entries[0]);
+    assert_eq!(
+        HuffmanArchive::decompress(&log[..consumed]).unwrap(),
+        entries[0]
+    );
+}
+
+#[test]
+fn torn_log_frames_are_cut_off() {
+    let path = scratch_file("torn.log");
+    let mut log = ArchiveLog::open(&path).unwrap();
+    for entry in [
+        &b"first entry\n"[..],
+        b"second entry\n",
+        b"torn by a crash\n",
+    ] {
+        log.append(entry).unwrap();
+    }
+    drop(log);
+
+    // The last write only partly reached the disk
+    let len = fs::metadata(&path).unwrap().len();
+    fs::OpenOptions::new()
+        .write(true)
+        .open(&path)
+        .unwrap()
+        .set_len(len - 3)
+        .unwrap();
+    assert!(HuffmanArchive::decompress_frames(&fs::read(&path).unwrap()).is_err());
+    assert_eq!(
+        ArchiveLog::read(&path).unwrap(),
+        b"first entry\nsecond entry\n"
+    );
+
+    let torn = encode_frame(b"torn by a crash\n", &Default::default()).len() as u64 - 3;
+    assert_eq!(ArchiveLog::recover(&path).unwrap(), torn);
+    assert_eq!(ArchiveLog::recover(&path).unwrap(), 0);
+
+    ArchiveLog::open(&path)
+        .unwrap()
+        .append(b"after recovery\n")
+        .unwrap();
+    assert_eq!(
+        HuffmanArchive::decompress_frames(&fs::read(&path).unwrap()).unwrap(),
+        b"first entry\nsecond entry\nafter recovery\n"
+    );
+}
+
+fn frames(chunks: &[&[u8]]) -> Vec<u8> {
+    chunks
+        .iter()
+        .flat_map(|chunk| encode_frame(chunk, &Default::default()))
+        .collect()
+}
+
+#[test]
+fn frames_end_on_size() {
+    let mut encoder = FrameEncoder::with_flush_policy(Vec::new(), 4, Duration::from_secs(3600));
+    encoder.write_all(b"0123456789").unwrap();
+    let output = encoder.finish().unwrap();
+    assert_eq!(output, frames(&[b"0123", b"4567", b"89"]));
+    assert_eq!(
+        HuffmanArchive::decompress_frames(&output).unwrap(),
+        b"0123456789"
+    );
+}
+
+#[test]
+fn frames_end_on_time() {
+    let interval = Duration::from_millis(20);
+
+    // Checked by the next write
+    let mut encoder = FrameEncoder::with_flush_policy(Vec::new(), 1000, interval);
+    encoder.write_all(b"early ").unwrap();
+    thread::sleep(interval * 2);
+    encoder.write_all(b"late").unwrap();
+    assert_eq!(encoder.finish().unwrap(), frames(&[b"early ", b"late"]));
+
+    // Or from a timer while no input arrives
+    let mut encoder = FrameEncoder::with_flush_policy(Vec::new(), 1000, interval);
+    encoder.write_all(b"early ").unwrap();
+    encoder.flush_if_due().unwrap();
+    thread::sleep(interval * 2);
+    encoder.flush_if_due().unwrap();
+    encoder.write_all(b"late").unwrap();
+    let output = encoder.finish().unwrap();
+    assert_eq!(output, frames(&[b"early ", b"late"]));
+    assert_eq!(
+        HuffmanArchive::decompress_frames(&output).unwrap(),
+        b"early late"
+    );
+}
@@ -0,0 +1,50 @@
+use compression::{FORMAT_VERSION, HuffmanArchive};
+
+// Inputs whose archives are committed under `tests/golden`, as `<name>.jp` for version 1 and
+// `<name>.v2.jp` for version 2. The archives must never change, a new encoding needs a new
+// format version.
+fn inputs() -> Vec<(&'static str, Vec<u8>)> {
+    let text = b"It was the best of times, it was the worst of times, it was the age of \
+        wisdom, it was the age of foolishness, it was the epoch of belief, it was the epoch of \
+        incredulity.\n";
+    let mut state = 7u32;
+    let varied = (0..=255u8)
+        .flat_map(|byte| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            std::iter::repeat_n(byte, 1 + (state >> 16) as usize % 40)
+        })
+        .collect();
+    vec![
+        ("empty", Vec::new()),
+        ("one-symbol", vec![b'z'; 100]),
+        ("text", text.to_vec()),
+        ("every-byte", (0..=255).collect()),
+        ("varied-lengths", varied),
+    ]
+}
+
+fn assert_stable(version: u8, suffix: &str) {
+    for (name, input) in inputs() {
+        let archive = HuffmanArchive::compress_with_format_version(&input, version)
+            .unwrap()
+            .into_vec();
+        let golden = std::fs::read(format!("tests/golden/{name}{suffix}.jp")).unwrap();
+        assert_eq!(archive, golden, "{name}");
+        assert_eq!(
+            HuffmanArchive::decompress(&golden).unwrap(),
+            input,
+            "{name}"
+        );
+    }
+}
+
+#[test]
+fn version_1_output_is_stable() {
+    assert_stable(1, "");
+}
+
+#[test]
+fn version_2_output_is_stable() {
+    assert_eq!(FORMAT_VERSION, 2);
+    assert_stable(2, ".v2");
+}
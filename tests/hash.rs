@@ -0,0 +1,18 @@
+#![cfg(feature = "hash")]
+
+use compression::{HuffmanArchive, HuffmanTree};
+use sha2::{Digest, Sha256};
+
+#[test]
+fn archive_hash_matches_the_archive() {
+    // Longer than one hashing interval, with a payload ending mid-byte
+    let data: Vec<u8> = (0..200_000u32).map(|i| (i % 7 + i % 13) as u8).collect();
+    for input in [&data[..], b"", b"abc"] {
+        let (archive, hash) = HuffmanArchive::compress_with_archive_hash(input);
+        assert_eq!(
+            archive,
+            HuffmanArchive::compress(input, &HuffmanTree::build(input)).into_vec()
+        );
+        assert_eq!(hash, <[u8; 32]>::from(Sha256::digest(&archive)));
+    }
+}
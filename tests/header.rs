@@ -0,0 +1,79 @@
+use compression::{
+    ArchiveHeader, DictMode, HeaderField, HuffmanArchive, HuffmanTree, Options, PrePass,
+};
+
+const TEXT: &[u8] = b"line one\r\nline two\r\n";
+
+// Archives between them setting every optional header field
+fn archives() -> Vec<Vec<u8>> {
+    let options = Options {
+        comment: Some("every field".into()),
+        payload_checksum: true,
+        ..Options::default()
+    };
+    let tree = HuffmanTree::build(TEXT);
+    // Every byte a varying number of times, stored as a plain length table
+    let mut state = 7u32;
+    let varied: Vec<u8> = (0..=255u8)
+        .flat_map(|byte| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            std::iter::repeat_n(byte, 1 + (state >> 16) as usize % 300)
+        })
+        .collect();
+    [
+        HuffmanArchive::compress_with_options(&varied, &HuffmanTree::build(&varied), &options),
+        HuffmanArchive::compress_text(TEXT, &options),
+        HuffmanArchive::compress_with_leaf_width(TEXT, &tree, 7, &options).unwrap(),
+        HuffmanArchive::compress_with_pre_passes(TEXT, &PrePass::ALL, &options),
+        HuffmanArchive::compress_records(&[TEXT, TEXT], &options),
+        HuffmanArchive::compress_double(TEXT, &options),
+        HuffmanArchive::compress_with_dictionary(TEXT, &tree, DictMode::Reference, &options)
+            .unwrap(),
+    ]
+    .into_iter()
+    .map(|archive| archive.into_vec())
+    .collect()
+}
+
+#[test]
+fn field_ranges_cover_the_header() {
+    let mut seen = Vec::new();
+    for archive in archives() {
+        let header = ArchiveHeader::parse(&archive).unwrap();
+        let ranges = header.field_ranges();
+
+        let mut end = 0;
+        for (field, range) in &ranges {
+            assert_eq!(range.start, end, "{field:?} does not follow on");
+            assert!(!range.is_empty(), "{field:?} is empty");
+            end = range.end;
+            if !seen.contains(field) {
+                seen.push(*field);
+            }
+        }
+        assert_eq!(end, header.byte_len());
+
+        let (field, magic) = &ranges[0];
+        assert_eq!(
+            (*field, &archive[magic.clone()]),
+            (HeaderField::Magic, &b"JP"[..])
+        );
+    }
+
+    for field in [
+        HeaderField::Checksum,
+        HeaderField::EntryCount,
+        HeaderField::LeafWidth,
+        HeaderField::PrePasses,
+        HeaderField::CommentLen,
+        HeaderField::Comment,
+        HeaderField::Dictionary,
+        HeaderField::CrlfCount,
+        HeaderField::PayloadLen,
+        HeaderField::PayloadChecksum,
+        HeaderField::FirstPassLen,
+        HeaderField::ExtendedFlags,
+    ] {
+        assert!(seen.contains(&field), "{field:?} never written");
+    }
+}
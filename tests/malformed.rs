@@ -0,0 +1,240 @@
+use bitvec::prelude::*;
+use compression::{
+    ArchiveBuilder, ArchiveHeader, DecompressError, FLAG_BLOCKS, FLAG_CHECKSUM, FLAG_EXTENDED,
+    FLAG_STORED, HeaderField, HuffmanArchive, HuffmanTree,
+};
+
+const HUGE: u64 = 1 << 62;
+
+// Overwrites header field `field` of `archive` with `value`
+fn patch(archive: &mut [u8], field: HeaderField, value: &[u8]) {
+    let header = ArchiveHeader::parse(archive).unwrap();
+    let (_, range) = header
+        .field_ranges()
+        .into_iter()
+        .find(|(found, _)| *found == field)
+        .unwrap();
+    archive[range].copy_from_slice(value);
+}
+
+// A block archive with one empty block whose frame is `frame`
+fn single_block(frame: &[u8]) -> Vec<u8> {
+    let mut body = BitVec::<u8, Msb0>::new();
+    body.extend_from_bitslice(0u64.to_be_bytes().view_bits::<Msb0>());
+    body.extend_from_bitslice(0u64.to_be_bytes().view_bits::<Msb0>());
+    body.extend_from_bitslice(frame.view_bits::<Msb0>());
+    ArchiveBuilder::new()
+        .flags(FLAG_BLOCKS)
+        .entry_count(Some(1))
+        .payload_bits(body)
+        .build()
+}
+
+#[test]
+fn block_frames_cannot_nest() {
+    let inner = single_block(&HuffmanArchive::store(b"", &Default::default()).into_vec());
+    assert_eq!(HuffmanArchive::decompress(&inner), Ok(Vec::new()));
+
+    let nested = single_block(&inner);
+    assert_eq!(
+        HuffmanArchive::decompress(&nested),
+        Err(DecompressError::InvalidBlockIndex)
+    );
+}
+
+#[test]
+fn huge_lengths_fail_before_allocating() {
+    let tree = HuffmanTree::build(b"ab");
+    let coded = ArchiveBuilder::new()
+        .tree(&tree)
+        .data_len(HUGE)
+        .payload_bits(bitvec![u8, Msb0; 0; 64])
+        .build();
+    assert_eq!(
+        HuffmanArchive::decompress(&coded),
+        Err(DecompressError::TruncatedPayload)
+    );
+
+    let stored = ArchiveBuilder::new()
+        .flags(FLAG_STORED)
+        .data_len(HUGE)
+        .build();
+    assert_eq!(
+        HuffmanArchive::decompress(&stored),
+        Err(DecompressError::TruncatedPayload)
+    );
+
+    let mut double =
+        HuffmanArchive::compress_double(b"abracadabra", &Default::default()).into_vec();
+    patch(&mut double, HeaderField::FirstPassLen, &HUGE.to_be_bytes());
+    assert_eq!(
+        HuffmanArchive::decompress(&double),
+        Err(DecompressError::TruncatedPayload)
+    );
+}
+
+// A block archive with `blocks` blocks that all point at one frame coding `len` bytes
+fn shared_frame(blocks: usize, len: usize) -> Vec<u8> {
+    let data = vec![b'a'; len];
+    let frame = HuffmanArchive::compress(&data, &HuffmanTree::build(&data));
+    let mut body = BitVec::<u8, Msb0>::new();
+    for _ in 0..blocks {
+        body.extend_from_bitslice(0u64.to_be_bytes().view_bits::<Msb0>());
+        body.extend_from_bitslice((len as u64).to_be_bytes().view_bits::<Msb0>());
+    }
+    body.extend_from_bitslice(&frame);
+    ArchiveBuilder::new()
+        .flags(FLAG_BLOCKS)
+        .entry_count(Some(blocks as u64))
+        .data_len((blocks * len) as u64)
+        .payload_bits(body)
+        .build()
+}
+
+#[test]
+fn shared_frames_cannot_multiply_without_end() {
+    let shared = shared_frame(100, 1000);
+    assert_eq!(HuffmanArchive::decompress(&shared), Ok(vec![b'a'; 100_000]));
+
+    // Some 80 KB claiming 20 MB, far past what sharing frames may add up to
+    let shared = shared_frame(5000, 4000);
+    assert_eq!(HuffmanArchive::decoded_len(&shared), Ok(20_000_000));
+    assert_eq!(
+        HuffmanArchive::decompress(&shared),
+        Err(DecompressError::InvalidBlockIndex)
+    );
+    assert_eq!(
+        HuffmanArchive::decompress_range(&shared, 0, 1),
+        Err(DecompressError::InvalidBlockIndex)
+    );
+}
+
+#[test]
+fn huge_index_lengths_are_rejected() {
+    let mut records =
+        HuffmanArchive::compress_records(&[b"one", b"two"], &Default::default()).into_vec();
+    let len_at = ArchiveHeader::parse(&records).unwrap().byte_len() + 8;
+    records[len_at..len_at + 8].copy_from_slice(&HUGE.to_be_bytes());
+    assert_eq!(
+        HuffmanArchive::decompress_record(&records, 0),
+        Err(DecompressError::TruncatedPayload)
+    );
+
+    let mut blocks =
+        HuffmanArchive::compress_blocks(b"abcdefgh", 4, &Default::default()).into_vec();
+    let len_at = ArchiveHeader::parse(&blocks).unwrap().byte_len() + 8;
+    blocks[len_at..len_at + 8].copy_from_slice(&HUGE.to_be_bytes());
+    assert_eq!(
+        HuffmanArchive::decompress_range(&blocks, 0, 1),
+        Err(DecompressError::InvalidBlockIndex)
+    );
+    assert_eq!(
+        HuffmanArchive::decompress(&blocks),
+        Err(DecompressError::InvalidBlockIndex)
+    );
+}
+
+// The CRC-32 `compress` stores for "ab"
+fn ab_checksum() -> u32 {
+    let archive = HuffmanArchive::compress(b"ab", &HuffmanTree::build(b"ab")).into_vec();
+    ArchiveHeader::parse(&archive).unwrap().checksum.unwrap()
+}
+
+// "ab" coded with the tree `build` gives it, with a checksum
+fn coded_ab(checksum: u32) -> ArchiveBuilder {
+    let tree = HuffmanTree::build(b"ab");
+    let codes = tree.get_encoding_table();
+    let mut payload = codes[b'a' as usize].clone();
+    payload.extend_from_bitslice(&codes[b'b' as usize]);
+    ArchiveBuilder::new()
+        .flags(FLAG_CHECKSUM)
+        .checksum(Some(checksum))
+        .tree(&tree)
+        .data_len(2)
+        .payload_bits(payload)
+}
+
+#[test]
+fn malformed_headers_are_rejected() {
+    let valid = coded_ab(ab_checksum()).build();
+    assert_eq!(HuffmanArchive::decompress(&valid), Ok(b"ab".to_vec()));
+
+    assert_eq!(
+        HuffmanArchive::decompress(&valid[..3]),
+        Err(DecompressError::TooShort)
+    );
+
+    let mut bad_magic = valid.clone();
+    bad_magic[1] = b'Q';
+    assert_eq!(
+        HuffmanArchive::decompress(&bad_magic),
+        Err(DecompressError::InvalidMagic)
+    );
+
+    let future = coded_ab(0).version(9).build();
+    assert_eq!(
+        HuffmanArchive::decompress(&future),
+        Err(DecompressError::UnsupportedVersion(9))
+    );
+
+    // Version 1 has no extended flags, the bit is as unknown as any other
+    let extended = coded_ab(ab_checksum())
+        .flags(FLAG_CHECKSUM | FLAG_EXTENDED)
+        .extended_flags(Some(0));
+    assert_eq!(
+        HuffmanArchive::decompress(&extended.build()),
+        Ok(b"ab".to_vec())
+    );
+    assert_eq!(
+        HuffmanArchive::decompress(&extended.clone().version(1).build()),
+        Err(DecompressError::UnsupportedFlags(FLAG_EXTENDED))
+    );
+    assert_eq!(
+        HuffmanArchive::decompress(&extended.extended_flags(Some(1 << 15)).build()),
+        Err(DecompressError::UnsupportedExtendedFlags(1 << 15))
+    );
+}
+
+#[test]
+fn malformed_bodies_are_rejected() {
+    let tree = HuffmanTree::build(b"ab");
+    let tree_bits = tree.serialize();
+
+    let truncated_tree = ArchiveBuilder::new()
+        .tree_bits(tree_bits[..tree_bits.len() - 4].to_bitvec())
+        .tree_len(tree_bits.len() as u64)
+        .data_len(1)
+        .build();
+    assert_eq!(
+        HuffmanArchive::decompress(&truncated_tree),
+        Err(DecompressError::TruncatedTree)
+    );
+
+    // A lone leaf would decode any number of symbols from no bits at all
+    let mut lone_leaf = bitvec![u8, Msb0; 1];
+    lone_leaf.extend_from_bitslice(b"a".view_bits::<Msb0>());
+    let lone_leaf = ArchiveBuilder::new()
+        .tree_len(lone_leaf.len() as u64)
+        .tree_bits(lone_leaf)
+        .data_len(1)
+        .build();
+    assert_eq!(
+        HuffmanArchive::decompress(&lone_leaf),
+        Err(DecompressError::InvalidTree)
+    );
+
+    let truncated_payload = coded_ab(ab_checksum()).data_len(40).build();
+    assert_eq!(
+        HuffmanArchive::decompress(&truncated_payload),
+        Err(DecompressError::TruncatedPayload)
+    );
+
+    let wrong_checksum = coded_ab(0xdead_beef).build();
+    assert_eq!(
+        HuffmanArchive::decompress(&wrong_checksum),
+        Err(DecompressError::ChecksumMismatch {
+            expected: 0xdead_beef,
+            actual: ab_checksum(),
+        })
+    );
+}
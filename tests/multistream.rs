@@ -0,0 +1,34 @@
+use compression::{DecompressError, HuffmanArchive};
+
+#[test]
+fn streams_round_trip_separately() {
+    let literals = b"literal bytes, mostly lowercase text".repeat(5);
+    let lengths: Vec<u8> = (0..200).map(|i| (i % 7) as u8).collect();
+    let streams: [&[u8]; 4] = [&literals, b"", &lengths, b"x"];
+
+    let archive = HuffmanArchive::compress_multistream(&streams, &Default::default()).into_vec();
+    assert_eq!(
+        HuffmanArchive::decompress_multistream(&archive).unwrap(),
+        streams
+    );
+    // Decoded as a whole, the streams run together
+    assert_eq!(
+        HuffmanArchive::decompress(&archive).unwrap(),
+        streams.concat()
+    );
+}
+
+#[test]
+fn plain_archives_are_not_multistream() {
+    let blocks = HuffmanArchive::compress_blocks(b"abcdef", 2, &Default::default()).into_vec();
+    assert_eq!(
+        HuffmanArchive::decompress_multistream(&blocks),
+        Err(DecompressError::NotMultistream)
+    );
+    assert_eq!(
+        HuffmanArchive::decompress_multistream(
+            &HuffmanArchive::compress_multistream(&[], &Default::default()).into_vec()
+        ),
+        Ok(Vec::new())
+    );
+}
@@ -0,0 +1,68 @@
+use compression::{ArchiveHeader, DecompressError, HeaderField, HuffmanArchive, PrePass};
+
+// Inputs that favour different candidates: plain coding, storing, delta and narrow leaves
+fn inputs() -> Vec<Vec<u8>> {
+    let mut state = 1u32;
+    let noise = (0..2000)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as u8
+        })
+        .collect();
+    vec![
+        Vec::new(),
+        b"x".to_vec(),
+        b"plain text with a small alphabet ".repeat(20),
+        noise,
+        (0..4000).map(|i| (i / 3) as u8).collect(),
+    ]
+}
+
+#[test]
+fn exhaustive_is_never_larger_than_best() {
+    for data in inputs() {
+        let best = HuffmanArchive::compress_best(&data, &Default::default());
+        let exhaustive =
+            HuffmanArchive::compress_exhaustive(&data, &PrePass::ALL, &Default::default());
+        assert!(
+            exhaustive.len() <= best.len(),
+            "{} > {}",
+            exhaustive.len(),
+            best.len()
+        );
+        assert_eq!(
+            HuffmanArchive::decompress(exhaustive.as_raw_slice()).unwrap(),
+            data
+        );
+    }
+}
+
+#[test]
+fn unknown_pre_passes_fail_before_decoding() {
+    let data = b"delta then move to front, delta then move to front";
+    let mut archive =
+        HuffmanArchive::compress_with_pre_passes(data, &PrePass::ALL, &Default::default())
+            .into_vec();
+    let description = HuffmanArchive::describe(&archive).unwrap();
+    assert_eq!(description.pre_passes, PrePass::ALL);
+    assert_eq!(HuffmanArchive::decompress(&archive).unwrap(), data);
+
+    // A pre-pass from a later version, alongside the known ones
+    let (_, range) = ArchiveHeader::parse(&archive)
+        .unwrap()
+        .field_ranges()
+        .into_iter()
+        .find(|(field, _)| *field == HeaderField::PrePasses)
+        .unwrap();
+    archive[range.start] |= 1 << 5;
+    assert_eq!(
+        HuffmanArchive::describe(&archive).err(),
+        Some(DecompressError::UnsupportedPrePass(1 << 5))
+    );
+    assert_eq!(
+        HuffmanArchive::decompress(&archive),
+        Err(DecompressError::UnsupportedPrePass(1 << 5))
+    );
+}
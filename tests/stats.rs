@@ -0,0 +1,46 @@
+use compression::{HuffmanArchive, HuffmanTree, Options};
+
+fn stats_of(data: &[u8]) -> compression::Stats {
+    let options = Options {
+        checksum: false,
+        ..Options::default()
+    };
+    let archive =
+        HuffmanArchive::compress_with_options(data, &HuffmanTree::build(data), &options).into_vec();
+    let (output, stats) = HuffmanArchive::decompress_with_stats(&archive).unwrap();
+    assert_eq!(output, data);
+    stats
+}
+
+#[test]
+fn every_code_bit_is_a_transition() {
+    // Direct table, codes of at most 8 bits
+    let text = b"so it goes, and so it goes again";
+    let stats = stats_of(text);
+    assert_eq!(stats.direct_lookups, text.len() as u64);
+    assert_eq!(stats.node_transitions, stats.bits_consumed);
+
+    // Root with two leaves, a bit per symbol
+    let two = b"abababbbaa";
+    let stats = stats_of(two);
+    assert_eq!(stats.node_transitions, two.len() as u64);
+    assert_eq!(stats.bits_consumed, two.len() as u64);
+
+    // A tree walk, with codes up to 12 bits
+    let deep: Vec<u8> = (0..12u32)
+        .flat_map(|depth| std::iter::repeat_n(depth as u8, 1 << depth))
+        .collect();
+    let stats = stats_of(&deep);
+    assert_eq!(stats.direct_lookups, 0);
+    assert_eq!(stats.node_transitions, stats.bits_consumed);
+}
+
+#[test]
+fn transitions_match_a_hand_count() {
+    // a ×5, b ×2, r ×2, c and d once: any huffman code for these takes 23 bits, e.g. a 1-bit
+    // a and 3-bit codes for the rest
+    let stats = stats_of(b"abracadabra");
+    assert_eq!(stats.node_transitions, 23);
+    assert_eq!(stats.direct_lookups, 11);
+    assert_eq!(stats.bits_consumed, 23);
+}
@@ -0,0 +1,203 @@
+use std::io::{self, Read, Write};
+
+use compression::{
+    ArchiveHeader, DecompressError, FrameEncoder, HuffmanArchive, HuffmanTree, PuhlDecoder,
+    PuhlEncoder, encode_frame,
+};
+
+// Hands out at most 7 bytes a read, like a slow pipe
+struct Trickle<'a>(&'a [u8]);
+
+impl Read for Trickle<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let count = buf.len().min(7);
+        self.0.read(&mut buf[..count])
+    }
+}
+
+// A pipe whose reader has gone away
+struct BrokenPipe;
+
+impl Write for BrokenPipe {
+    fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+        Err(io::ErrorKind::BrokenPipe.into())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Err(io::ErrorKind::BrokenPipe.into())
+    }
+}
+
+#[test]
+fn io_copy_round_trips() {
+    let data = b"the quick brown fox jumps over the lazy dog ".repeat(50);
+
+    let mut encoder = PuhlEncoder::new(Vec::new());
+    io::copy(&mut &data[..], &mut encoder).unwrap();
+    let archive = encoder.finish().unwrap();
+
+    let mut decoder = PuhlDecoder::new(&archive[..]);
+    let mut decoded = Vec::new();
+    io::copy(&mut decoder, &mut decoded).unwrap();
+    assert_eq!(decoded, data);
+}
+
+#[test]
+fn dropping_the_encoder_writes_the_archive() {
+    let mut archive = Vec::new();
+    {
+        let mut encoder = PuhlEncoder::new(&mut archive);
+        encoder.write_all(b"written on drop").unwrap();
+    }
+
+    let mut decoded = String::new();
+    PuhlDecoder::new(&archive[..])
+        .read_to_string(&mut decoded)
+        .unwrap();
+    assert_eq!(decoded, "written on drop");
+
+    // Frames too, the last one is only buffered until then
+    let mut frames = Vec::new();
+    {
+        let mut encoder = FrameEncoder::new(&mut frames, 8);
+        encoder
+            .write_all(b"the last frame is written on drop")
+            .unwrap();
+    }
+    assert_eq!(
+        HuffmanArchive::decompress_frames(&frames).unwrap(),
+        b"the last frame is written on drop"
+    );
+}
+
+#[test]
+fn broken_pipes_are_errors() {
+    let mut encoder = PuhlEncoder::new(BrokenPipe);
+    encoder.write_all(b"nobody reads this").unwrap();
+    let err = encoder.finish().err().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+
+    // The second write finds the first frame full and fails without taking any input
+    let mut frames = FrameEncoder::new(BrokenPipe, 4);
+    assert_eq!(frames.write(b"abcdef").unwrap(), 4);
+    let err = frames.write(b"ef").unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+    let err = frames.finish().err().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+}
+
+#[test]
+fn tee_writes_the_whole_output_twice() {
+    let frames = [&b"first frame, "[..], b"", b"and the second"];
+    let archive: Vec<u8> = frames
+        .iter()
+        .flat_map(|frame| encode_frame(frame, &Default::default()))
+        .collect();
+
+    let (mut stored, mut processed) = (Vec::new(), Vec::new());
+    HuffmanArchive::decompress_tee(&archive, &mut stored, &mut processed).unwrap();
+    assert_eq!(stored, frames.concat());
+    assert_eq!(processed, stored);
+
+    // Frames before a corrupt one still reach both writers
+    let mut corrupt = archive.clone();
+    corrupt.extend_from_slice(b"JP?");
+    let (mut stored, mut processed) = (Vec::new(), Vec::new());
+    let err = HuffmanArchive::decompress_tee(&corrupt, &mut stored, &mut processed).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(stored, frames.concat());
+    assert_eq!(processed, stored);
+}
+
+#[test]
+fn progress_rises_to_one() {
+    let data = b"read a little at a time ".repeat(40);
+    let archive = HuffmanArchive::compress(&data, &HuffmanTree::build(&data));
+    let mut decoder = PuhlDecoder::new(archive.as_raw_slice());
+    assert_eq!(decoder.progress(), 0.0);
+
+    let mut chunk = [0; 100];
+    let mut last = 0.0;
+    loop {
+        let read = decoder.read(&mut chunk).unwrap();
+        let progress = decoder.progress();
+        assert!(
+            progress >= last && progress <= 1.0,
+            "{last} then {progress}"
+        );
+        if read == 0 {
+            break;
+        }
+        assert!(progress > last);
+        last = progress;
+    }
+    assert_eq!(last, 1.0);
+
+    let empty = HuffmanArchive::compress(b"", &HuffmanTree::build(b""));
+    let mut decoder = PuhlDecoder::new(empty.as_raw_slice());
+    assert_eq!(decoder.read(&mut chunk).unwrap(), 0);
+    assert_eq!(decoder.progress(), 1.0);
+}
+
+#[test]
+fn frames_decode_one_at_a_time() {
+    let data: Vec<u8> = (0..60_000u32).map(|i| (i * i % 251) as u8).collect();
+    let mut encoder = FrameEncoder::new(Vec::new(), 25_000);
+    encoder.write_all(&data).unwrap();
+    let archive = encoder.finish().unwrap();
+
+    let mut decoder = PuhlDecoder::new(Trickle(&archive));
+    let mut first = vec![0; 25_000];
+    decoder.read_exact(&mut first).unwrap();
+    assert_eq!(first, data[..25_000]);
+    // Only the first frame's worth of input was needed for it
+    assert!(decoder.get_ref().0.len() > archive.len() / 3);
+
+    let mut rest = Vec::new();
+    decoder.read_to_end(&mut rest).unwrap();
+    assert_eq!(rest, data[25_000..]);
+    assert_eq!(decoder.progress(), 1.0);
+
+    // A frame cut short fails once the input ends
+    let mut decoder = PuhlDecoder::new(Trickle(&archive[..archive.len() - 1]));
+    let err = decoder.read_to_end(&mut Vec::new()).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn corrupt_streams_fail_at_finish() {
+    let first = b"the first frame is fine";
+    let data = b"verified even when nobody reads to the end";
+    let archive = HuffmanArchive::compress(data, &HuffmanTree::build(data)).into_vec();
+    assert!(PuhlDecoder::new(&archive[..]).finish().is_ok());
+
+    // A good frame, then one with the same codes but a stored checksum that is off
+    let mut corrupt = archive.clone();
+    corrupt[ArchiveHeader::MIN_LEN] ^= 0xff;
+    let stream = [encode_frame(first, &Default::default()), corrupt].concat();
+
+    // Stopping partway through the good frame, finish still decodes the rest
+    let mut decoder = PuhlDecoder::new(&stream[..]);
+    let mut buf = [0; 9];
+    decoder.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"the first");
+    let err = decoder.finish().err().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    let inner = err
+        .into_inner()
+        .unwrap()
+        .downcast::<DecompressError>()
+        .unwrap();
+    assert!(matches!(*inner, DecompressError::ChecksumMismatch { .. }));
+
+    // Reading on reports it from the corrupt frame on, then finish does too
+    let mut decoder = PuhlDecoder::new(&stream[..]);
+    let mut buf = [0; 23];
+    decoder.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, first);
+    for _ in 0..2 {
+        let err = decoder.read(&mut buf).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+    assert!(decoder.finish().is_err());
+}
@@ -0,0 +1,294 @@
+use std::cmp::Ordering;
+
+use bitvec::prelude::*;
+use compression::{CompressError, DecompressError, HuffmanArchive, HuffmanTree, codebook_diff};
+
+const SOURCE: &[u8] = include_bytes!("../src/tree.rs");
+
+fn round_trip(data: &[u8], tree: &HuffmanTree) {
+    let archive = HuffmanArchive::compress(data, tree).into_vec();
+    assert_eq!(HuffmanArchive::decompress(&archive).unwrap(), data);
+}
+
+#[test]
+fn code_length_extremes_cover_present_bytes_only() {
+    assert_eq!(
+        HuffmanTree::build(b"aaaabbc").code_length_extremes(),
+        (1, 2)
+    );
+
+    // An incomplete code, whose holes at depths 2 and 3 get padding leaves for b
+    let mut lengths = [0; 256];
+    lengths[b'a' as usize] = 1;
+    lengths[b'b' as usize] = 3;
+    let tree = HuffmanTree::from_code_lengths(&lengths).unwrap();
+    assert_eq!(tree.code_length_extremes(), (1, 3));
+}
+
+#[test]
+fn fast_trees_decode() {
+    let skewed: Vec<u8> = (0..5000u32).map(|i| (i * i % 97) as u8).collect();
+    for data in [SOURCE, &skewed, b"a", b""] {
+        let tree = HuffmanTree::build_fast(data);
+        round_trip(data, &tree);
+        if !data.is_empty() {
+            let exact = HuffmanArchive::payload_bit_len_with_tree(data, &HuffmanTree::build(data));
+            let fast = HuffmanArchive::payload_bit_len_with_tree(data, &tree);
+            assert!(fast.unwrap() >= exact.unwrap());
+        }
+    }
+}
+
+#[test]
+fn min_length_codes_are_long_enough() {
+    // One byte makes up most of the input and would get a 1-bit code
+    let mut skewed = vec![b'e'; 4000];
+    skewed.extend(SOURCE.iter().take(1000));
+
+    for min_len in [1, 2, 3, 5, 8] {
+        let tree = HuffmanTree::build_min_length(&skewed, min_len);
+        let lengths = tree.code_lengths();
+        assert!(
+            lengths.iter().all(|&l| l == 0 || l >= min_len),
+            "min_len {min_len}"
+        );
+        assert!(HuffmanTree::from_code_lengths(&lengths).is_some());
+        round_trip(&skewed, &tree);
+    }
+    assert_eq!(
+        HuffmanTree::build_min_length(&skewed, 1)
+            .code_length_extremes()
+            .0,
+        1
+    );
+}
+
+#[test]
+fn average_code_length_is_within_a_bit_of_the_entropy() {
+    for data in [SOURCE, b"aaaaaaab", b"abcdefgh"] {
+        let frequency = HuffmanTree::frequencies(data);
+        let total = data.len() as f64;
+        let entropy: f64 = frequency
+            .iter()
+            .filter(|&&freq| freq > 0)
+            .map(|&freq| -(freq as f64 / total) * (freq as f64 / total).log2())
+            .sum();
+        let average = HuffmanTree::build(data).average_code_length(&frequency);
+        assert!(entropy <= average && average < entropy + 1.0);
+    }
+}
+
+#[test]
+fn heap_merges_the_lowest_frequencies_first() {
+    // In debug builds `build` asserts every merge takes the two lowest frequencies, which a
+    // reversed `Ord` on nodes would break. Its payload must also stay optimal.
+    let skewed: Vec<u8> = (0..20u8)
+        .flat_map(|byte| std::iter::repeat_n(byte, 1 + byte as usize * byte as usize))
+        .collect();
+    for data in [SOURCE, &skewed] {
+        let frequency = HuffmanTree::frequencies(data);
+        let optimal: usize = compression::code_lengths_from_frequencies(&frequency)
+            .iter()
+            .zip(frequency)
+            .map(|(&length, freq)| length as usize * freq)
+            .sum();
+        let built = HuffmanArchive::payload_bit_len_with_tree(data, &HuffmanTree::build(data));
+        assert_eq!(built.unwrap(), optimal);
+
+        // What a reversed comparator gives: highest frequencies merged first, a worse code
+        let reversed =
+            HuffmanTree::build_with_comparator(data, |a, b| b.frequency().cmp(&a.frequency()));
+        let reversed = HuffmanArchive::payload_bit_len_with_tree(data, &reversed).unwrap();
+        assert!(reversed > optimal);
+    }
+}
+
+#[test]
+fn narrow_leaves_round_trip() {
+    let data = b"plain ascii text, seven bits a byte";
+    let tree = HuffmanTree::build(data);
+    assert_eq!(tree.leaf_width(), 7);
+
+    let narrow = tree.serialize_with_leaf_width(7);
+    assert!(narrow.len() < tree.serialize().len());
+    let read = HuffmanTree::deserialize_shape_with_leaf_width(&mut narrow.iter().by_vals(), 7);
+    assert_eq!(
+        read.unwrap().get_encoding_table(),
+        tree.get_encoding_table()
+    );
+
+    let archive =
+        HuffmanArchive::compress_with_leaf_width(data, &tree, 7, &Default::default()).unwrap();
+    assert_eq!(
+        HuffmanArchive::decompress(archive.as_raw_slice()).unwrap(),
+        data
+    );
+    assert_eq!(
+        HuffmanArchive::compress_with_leaf_width(data, &tree, 6, &Default::default()),
+        Err(CompressError::LeafWidthTooNarrow {
+            width: 6,
+            needed: 7
+        })
+    );
+}
+
+#[test]
+fn codebook_diff_lists_changed_lengths() {
+    let before = HuffmanTree::build(b"aaab");
+    let after = HuffmanTree::build(b"aaabc");
+    assert_eq!(codebook_diff(&before, &after), [(b'b', 1, 2), (b'c', 0, 2)]);
+    assert_eq!(codebook_diff(&after, &before), [(b'b', 2, 1), (b'c', 2, 0)]);
+    assert!(codebook_diff(&after, &HuffmanTree::build(b"cbaaa")).is_empty());
+}
+
+#[test]
+fn deep_trees_deserialize_without_recursion() {
+    // A chain 255 internal nodes deep, the deepest a tree over bytes can be
+    let mut bits = BitVec::<u8, Msb0>::new();
+    for byte in 0..=254u8 {
+        bits.push(false);
+        bits.push(true);
+        bits.extend_from_bitslice(byte.view_bits::<Msb0>());
+    }
+    bits.push(true);
+    bits.extend_from_bitslice(255u8.view_bits::<Msb0>());
+
+    let tree = HuffmanTree::deserialize_shape(&mut bits.iter().by_vals()).unwrap();
+    assert_eq!(tree.serialize(), bits);
+    assert_eq!(tree.code_length_extremes(), (1, 255));
+    assert_eq!(tree.node_count(), 511);
+    let data: Vec<u8> = (0..=255).rev().collect();
+    round_trip(&data, &tree);
+
+    // One level deeper has codes too long for their `u8` lengths
+    let mut deeper = bitvec![u8, Msb0; 0];
+    deeper.extend_from_bitslice(&bits);
+    assert_eq!(
+        HuffmanTree::deserialize_shape(&mut deeper.iter().by_vals()).err(),
+        Some(DecompressError::InvalidTree)
+    );
+}
+
+#[test]
+fn smoothing_shortens_the_longest_codes() {
+    // Powers of two counts, the skew that gives the deepest tree
+    let data: Vec<u8> = (0..14u8)
+        .flat_map(|byte| std::iter::repeat_n(b'a' + byte, 1 << byte))
+        .collect();
+
+    let mut previous = u8::MAX;
+    for floor in [0, 10, 100, 1000, 100_000] {
+        let tree = HuffmanTree::build_smoothed(&data, floor);
+        let (_, longest) = tree.code_length_extremes();
+        assert!(longest <= previous, "floor {floor}");
+        previous = longest;
+        round_trip(&data, &tree);
+    }
+    assert_eq!(
+        HuffmanTree::build_smoothed(&data, 0)
+            .code_length_extremes()
+            .1,
+        13
+    );
+    // Frequencies so close together that the code is as balanced as 14 bytes allow
+    assert_eq!(previous, 4);
+}
+
+#[test]
+fn any_comparator_gives_a_decodable_code() {
+    let frequency = HuffmanTree::frequencies(SOURCE);
+    let payload_bits = |tree: &HuffmanTree| -> usize {
+        frequency
+            .iter()
+            .zip(tree.code_lengths())
+            .map(|(&freq, length)| freq * length as usize)
+            .sum()
+    };
+    let huffman =
+        HuffmanTree::build_with_comparator(SOURCE, |a, b| a.frequency().cmp(&b.frequency()));
+    assert_eq!(
+        payload_bits(&huffman),
+        payload_bits(&HuffmanTree::build(SOURCE))
+    );
+    round_trip(SOURCE, &huffman);
+
+    // The heaviest first, by byte value, and an order that flips on every call
+    let mut calls = 0u32;
+    let trees = [
+        HuffmanTree::build_with_comparator(SOURCE, |a, b| b.frequency().cmp(&a.frequency())),
+        HuffmanTree::build_with_comparator(SOURCE, |a, b| a.byte().cmp(&b.byte())),
+        HuffmanTree::build_with_comparator(SOURCE, |_, _| {
+            calls += 1;
+            if calls.is_multiple_of(3) {
+                Ordering::Less
+            } else {
+                Ordering::Greater
+            }
+        }),
+    ];
+    for tree in &trees {
+        assert!(payload_bits(tree) >= payload_bits(&huffman));
+        round_trip(SOURCE, tree);
+    }
+}
+
+// Sum of 2^-length over the codes, as a multiple of 2^-longest
+fn kraft_sum(tree: &HuffmanTree) -> (u128, u128) {
+    let lengths: Vec<u32> = tree
+        .get_encoding_table()
+        .iter()
+        .filter(|code| !code.is_empty())
+        .map(|code| code.len() as u32)
+        .collect();
+    let longest = *lengths.iter().max().unwrap();
+    let sum = lengths.iter().map(|&length| 1 << (longest - length)).sum();
+    (sum, 1 << longest)
+}
+
+// `build_min_length` is left out, its codes may leave codespace to padding leaves
+#[test]
+fn built_codes_are_complete() {
+    let skewed: Vec<u8> = (0..20u8)
+        .flat_map(|byte| std::iter::repeat_n(byte, 1 << byte))
+        .collect();
+    let trees = [
+        // Paired with a dummy leaf for byte 0
+        HuffmanTree::build(b"zzzz"),
+        HuffmanTree::build(SOURCE),
+        HuffmanTree::build(&skewed),
+        HuffmanTree::build_fast(SOURCE),
+        HuffmanTree::build_smoothed(&skewed, 50),
+        HuffmanTree::build_with_comparator(SOURCE, |a, b| a.byte().cmp(&b.byte())),
+        HuffmanTree::from_code_lengths(&HuffmanTree::build(SOURCE).code_lengths()).unwrap(),
+    ];
+    for tree in &trees {
+        let (sum, one) = kraft_sum(tree);
+        assert_eq!(sum, one);
+    }
+}
+
+#[test]
+fn node_counts_follow_the_leaves() {
+    for (data, leaves) in [
+        (&b"ab"[..], 2),
+        (b"abracadabra", 5),
+        (
+            SOURCE,
+            HuffmanTree::frequencies(SOURCE)
+                .iter()
+                .filter(|&&f| f != 0)
+                .count(),
+        ),
+        (&(0..=255).collect::<Vec<u8>>(), 256),
+    ] {
+        let tree = HuffmanTree::build(data);
+        assert_eq!(tree.node_count(), 2 * leaves - 1);
+        assert_eq!(
+            tree.heap_bytes(),
+            tree.node_count() * std::mem::size_of::<compression::HuffmanNode>()
+        );
+    }
+
+    // A single byte is paired with a dummy leaf
+    assert_eq!(HuffmanTree::build(b"zzz").node_count(), 3);
+}
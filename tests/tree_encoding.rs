@@ -0,0 +1,133 @@
+use compression::{
+    ArchiveHeader, EXTENDED_FLAG_LENGTH_TABLE, FLAG_CODE_LENGTHS, HuffmanArchive, HuffmanTree,
+    Options, deserialize_code_length_table, deserialize_code_lengths_rle,
+    serialize_code_length_table, serialize_code_lengths_rle,
+};
+
+// Every byte value, each repeated a pseudo-random number of times, so code lengths vary from
+// one byte to the next and neither the shape nor the run-length encoding is small
+fn varied_lengths() -> Vec<u8> {
+    let mut state = 7u32;
+    let mut data = Vec::new();
+    for byte in 0..=255u8 {
+        state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+        let count = 1 + (state >> 16) as usize % 300;
+        data.extend(std::iter::repeat_n(byte, count));
+    }
+    data
+}
+
+fn tree_flags(archive: &[u8]) -> u16 {
+    let header = ArchiveHeader::parse(archive).unwrap();
+    header.flags & FLAG_CODE_LENGTHS
+        | header.extended_flags.unwrap_or(0) & EXTENDED_FLAG_LENGTH_TABLE
+}
+
+#[test]
+fn length_table_round_trips() {
+    let lengths = HuffmanTree::build(&varied_lengths()).code_lengths();
+    let bits = serialize_code_length_table(&lengths);
+    assert_eq!(bits.len(), 256 * 8);
+    assert_eq!(
+        deserialize_code_length_table(&mut bits.iter().by_vals()),
+        Ok(lengths)
+    );
+}
+
+#[test]
+fn length_table_is_used_when_smallest() {
+    let data = varied_lengths();
+    let tree = HuffmanTree::build(&data);
+    let costs = tree.tree_serialization_costs();
+    assert!(costs.code_lengths < costs.shape && costs.code_lengths < costs.rle_code_lengths);
+
+    let options = Options::default();
+    let every_byte: Vec<u8> = (0..=255).collect();
+    let archives = [
+        HuffmanArchive::compress(&data, &tree),
+        HuffmanArchive::compress_with_pre_passes(&data, &[], &options),
+        HuffmanArchive::compress_restricted(&data, &every_byte, &options).unwrap(),
+    ];
+    for archive in archives {
+        assert_eq!(
+            HuffmanArchive::compressed_bit_len(&data, &options),
+            archive.len()
+        );
+        let archive = archive.into_vec();
+        assert_eq!(tree_flags(&archive), EXTENDED_FLAG_LENGTH_TABLE);
+        assert_eq!(HuffmanArchive::decompress(&archive).unwrap(), data);
+    }
+}
+
+#[test]
+fn sparse_alphabets_keep_the_run_length_encoding() {
+    let data = b"aaaaaaaabbbbccd".repeat(10);
+    let archive = HuffmanArchive::compress(&data, &HuffmanTree::build(&data)).into_vec();
+    assert_ne!(tree_flags(&archive), EXTENDED_FLAG_LENGTH_TABLE);
+    assert_eq!(HuffmanArchive::decompress(&archive).unwrap(), data);
+}
+
+#[test]
+fn tree_len_is_the_written_tree() {
+    for data in [&b"ab"[..], b"mississippi", &varied_lengths()] {
+        let tree = HuffmanTree::build(data);
+        let archive = HuffmanArchive::compress(data, &tree).into_vec();
+        let header = ArchiveHeader::parse(&archive).unwrap();
+        assert_eq!(
+            header.tree_len as usize,
+            tree.tree_serialization_costs().smallest()
+        );
+
+        let width = tree.leaf_width();
+        let narrow =
+            HuffmanArchive::compress_with_leaf_width(data, &tree, width, &Options::default())
+                .unwrap()
+                .into_vec();
+        assert_eq!(
+            ArchiveHeader::parse(&narrow).unwrap().tree_len as usize,
+            tree.serialize_with_leaf_width(width).len()
+        );
+    }
+}
+
+#[test]
+fn run_length_encoded_lengths_round_trip() {
+    // Runs of zeros and of equal lengths longer than one token holds
+    let mut long_runs = [0u8; 256];
+    long_runs[40..80].fill(8);
+    long_runs[255] = 1;
+    for lengths in [
+        HuffmanTree::build(&varied_lengths()).code_lengths(),
+        HuffmanTree::build(b"aaaaaaaabbbbccd").code_lengths(),
+        long_runs,
+    ] {
+        let bits = serialize_code_lengths_rle(&lengths);
+        assert_eq!(
+            deserialize_code_lengths_rle(&mut bits.iter().by_vals()),
+            Ok(lengths)
+        );
+    }
+
+    let sparse = HuffmanTree::build(b"aaaaaaaabbbbccd").code_lengths();
+    assert!(
+        serialize_code_lengths_rle(&sparse).len() < serialize_code_length_table(&sparse).len() / 10
+    );
+}
+
+#[test]
+fn serialization_costs_are_the_serialized_lengths() {
+    for data in [&b""[..], b"z", b"mississippi", &varied_lengths()] {
+        let tree = HuffmanTree::build(data);
+        let lengths = tree.code_lengths();
+        let costs = tree.tree_serialization_costs();
+        assert_eq!(costs.shape, tree.serialize().len());
+        assert_eq!(
+            costs.code_lengths,
+            serialize_code_length_table(&lengths).len()
+        );
+        assert_eq!(
+            costs.rle_code_lengths,
+            serialize_code_lengths_rle(&lengths).len()
+        );
+    }
+}
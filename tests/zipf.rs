@@ -0,0 +1,34 @@
+use compression::analyze_zipf;
+
+// Byte `rank - 1` repeated in proportion to rank^-exponent
+fn zipf_input(exponent: f64, symbols: u8) -> Vec<u8> {
+    (1..=symbols)
+        .flat_map(|rank| {
+            let count = (100_000.0 * (rank as f64).powf(-exponent)).round() as usize;
+            std::iter::repeat_n(rank - 1, count)
+        })
+        .collect()
+}
+
+#[test]
+fn zipf_inputs_fit_their_exponent() {
+    for exponent in [0.5, 1.0, 1.5] {
+        let fit = analyze_zipf(&zipf_input(exponent, 60));
+        assert_eq!(fit.symbols, 60);
+        assert!((fit.exponent - exponent).abs() < 0.01, "{fit:?}");
+        assert!(fit.rms_error < 0.01, "{fit:?}");
+    }
+
+    // Uniform input has no slope at all
+    let uniform: Vec<u8> = (0..=255).cycle().take(256 * 10).collect();
+    let fit = analyze_zipf(&uniform);
+    assert!(fit.exponent.abs() < 1e-9 && fit.rms_error < 1e-9, "{fit:?}");
+}
+
+#[test]
+fn single_symbols_have_nothing_to_fit() {
+    for data in [&b""[..], b"zzzz"] {
+        let fit = analyze_zipf(data);
+        assert_eq!((fit.exponent, fit.rms_error), (0.0, 0.0));
+    }
+}